@@ -0,0 +1,27 @@
+//! Times lexing sources made mostly of long comments or long strings.
+//! Run with `cargo bench --bench lex`.
+use std::{hint::black_box, time::Instant};
+
+use rlispy::lexer::lex;
+
+fn bench(name: &str, source: &str) {
+    let runs = 100;
+    let start = Instant::now();
+
+    for _ in 0..runs {
+        black_box(lex(black_box(source)).unwrap());
+    }
+
+    println!("{}: {:?} per run over {} KiB", name, start.elapsed() / runs, source.len() / 1024);
+}
+
+fn main() {
+    let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor. ".repeat(8);
+    let comments = format!(";; {}\n(f x)\n", text).repeat(1000);
+    let strings = format!("(print \"{}\")\n", text).repeat(1000);
+    let escaped = format!("(print \"{}\\n\")\n", text).repeat(1000);
+
+    bench("long comments", &comments);
+    bench("long strings", &strings);
+    bench("long strings with escapes", &escaped);
+}
@@ -0,0 +1,92 @@
+//! Times parsing large list literals and counts the allocations made by the parser,
+//! which presizes each collection from the number of forms directly inside it,
+//! and compares lexing and parsing into owned forms with borrowing their text from the source.
+//! Run with `cargo bench --bench parse`.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rlispy::{lexer::lex, parser::{borrowed::parse_borrowed, parse_str, Parser}};
+
+/// Counts the allocations going through the system allocator.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn bench(name: &str, source: &str) {
+    let runs = 100;
+    let tokens = lex(source).unwrap();
+    let mut elapsed = Duration::ZERO;
+    let mut allocations = 0;
+
+    for _ in 0..runs {
+        // Only the parser is measured, not the copy of the tokens it consumes
+        let tokens = tokens.clone();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+
+        black_box(Parser::new(black_box(tokens)).parse_all().unwrap());
+
+        elapsed += start.elapsed();
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+
+    println!("{}: {:?} and {} allocations per run over {} tokens", name, elapsed / runs, allocations / runs as usize, tokens.len());
+}
+
+/// Counts the allocations made by `parse` over the source, along with the time it takes.
+fn measure<T>(source: &str, parse: impl Fn(&str) -> T) -> (Duration, usize) {
+    let runs = 100;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for _ in 0..runs {
+        black_box(parse(black_box(source)));
+    }
+
+    (start.elapsed() / runs, (ALLOCATIONS.load(Ordering::Relaxed) - before) / runs as usize)
+}
+
+fn bench_borrowed(name: &str, source: &str) {
+    let (owned_time, owned_allocations) = measure(source, |source| black_box(parse_str(source).unwrap()).len());
+    let (borrowed_time, borrowed_allocations) = measure(source, |source| black_box(parse_borrowed(source).unwrap()).len());
+
+    println!(
+        "{}: {:?} and {} allocations per run owned, {:?} and {} borrowed",
+        name, owned_time, owned_allocations, borrowed_time, borrowed_allocations,
+    );
+}
+
+fn main() {
+    let integers = format!("[{}]", (0..100_000).map(|i| i.to_string()).collect::<Vec<_>>().join(" "));
+    let nested = format!("[{}]", "[1 2 3 4 5 6 7 8 9 10] ".repeat(10_000));
+
+    bench("large list of integers", &integers);
+    bench("large list of small lists", &nested);
+
+    let calls = "(defn greet [name] (str \"hello, \" name :loud)) ; say hi\n".repeat(10_000);
+
+    bench_borrowed("many calls of symbols and strings", &calls);
+}
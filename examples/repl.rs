@@ -0,0 +1,43 @@
+use std::io::{self, BufRead, Write};
+
+use rlispy::parser::{is_complete, parse_str};
+
+fn prompt(prompt: &str) {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+}
+
+fn main() {
+    let mut source = String::new();
+
+    prompt("> ");
+
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                break;
+            },
+        };
+
+        source.push_str(&line);
+        source.push('\n');
+
+        // Keep reading lines until the forms are complete
+        if !is_complete(&source) {
+            prompt(".. ");
+            continue;
+        }
+
+        match parse_str(&source) {
+            Ok(forms) => for form in forms {
+                println!("{:#?}", form);
+            },
+            Err(err) => eprintln!("{}", err.render(&source)),
+        }
+
+        source.clear();
+        prompt("> ");
+    }
+}
@@ -0,0 +1,277 @@
+//! Reusable traversals and transformations over [`Form`] trees.
+use std::collections::HashMap;
+
+use crate::{lexer::symbol::Symbol, parser::Form};
+
+/// Returns every form in the tree, including `form` itself, for which `pred` holds, in pre-order.
+pub fn find_all(form: &Form, pred: impl Fn(&Form) -> bool) -> Vec<&Form> {
+    fold(form, Vec::new(), |mut found, form| {
+        if pred(form) {
+            found.push(form);
+        }
+        found
+    })
+}
+
+/// Replaces every form for which `pred` holds with the result of `replacement`.
+/// The replacements themselves are not searched again.
+pub fn replace_all(form: Form, pred: impl Fn(&Form) -> bool, replacement: impl Fn(Form) -> Form) -> Form {
+    fn go(form: Form, pred: &dyn Fn(&Form) -> bool, replacement: &dyn Fn(Form) -> Form) -> Form {
+        if pred(&form) {
+            replacement(form)
+        } else {
+            form.map_forms(|child| go(child, pred, replacement))
+        }
+    }
+
+    go(form, &pred, &replacement)
+}
+
+/// Combines every form in the tree, including `form` itself, into an accumulator, in pre-order.
+pub fn fold<'a, T>(form: &'a Form, init: T, mut f: impl FnMut(T, &'a Form) -> T) -> T {
+    fn go<'a, T>(form: &'a Form, acc: T, f: &mut dyn FnMut(T, &'a Form) -> T) -> T {
+        let acc = f(acc, form);
+
+        form.children()
+            .into_iter()
+            .fold(acc, |acc, child| go(child, acc, f))
+    }
+
+    go(form, init, &mut f)
+}
+
+/// Renames every symbol found in `renames` to its new name, for hygienic macro expansion.
+/// Quoted forms are data, so they are left untouched.
+pub fn rename(form: Form, renames: &HashMap<Symbol, Symbol>) -> Form {
+    match form {
+        Form::Symbol(symbol) => match renames.get(&symbol) {
+            Some(renamed) => Form::Symbol(Box::new(renamed.clone())),
+            None => Form::Symbol(symbol),
+        },
+        form @ Form::Quote(_) => form,
+        form => form.map_forms(|child| rename(child, renames)),
+    }
+}
+
+/// Keyword arguments returned by [`split_kwargs`], as the keyword name and its value.
+pub type KeywordArgs<'a> = Vec<(&'a str, &'a Form)>;
+
+/// Splits the arguments of a `Call`, after its head, into the positional ones and the trailing
+/// keyword/value pairs, so `(f a b :x 1 :y 2)` gives `[a, b]` and `[("x", 1), ("y", 2)]`.
+///
+/// Errors on other forms, on a keyword with no value and on a positional argument after a keyword.
+pub fn split_kwargs(call: &Form) -> Result<(Vec<&Form>, KeywordArgs<'_>), String> {
+    let Form::Call(forms) = call else {
+        return Err(format!("Expected a call, found {}", call.describe()));
+    };
+
+    let args = forms.get(1..).unwrap_or_default();
+    let split = args.iter().position(|arg| matches!(arg, Form::Keyword(_))).unwrap_or(args.len());
+    let (positional, keywords) = args.split_at(split);
+
+    let kwargs = keywords.chunks(2)
+        .map(|pair| match pair {
+            [Form::Keyword(key), value] => Ok((key.as_str(), value)),
+            [Form::Keyword(key)] => Err(format!("The keyword argument `{}` has no value", key)),
+            [arg, ..] => Err(format!("Expected a keyword argument, found {}", arg.describe())),
+            [] => unreachable!("chunks are never empty"),
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((positional.iter().collect(), kwargs))
+}
+
+/// The operators folded by [`fold_constants`], taken to be the usual arithmetic on numbers.
+pub const FOLDED_OPERATORS: [&str; 4] = ["+", "-", "*", "/"];
+
+/// Replaces every call of a [`FOLDED_OPERATORS`] operator on number literals with its result,
+/// so `(* 2 (+ 1 1))` becomes `4`. Quoted forms are data, so they are left untouched.
+///
+/// Only folds when the result is certain: integer operations that overflow, integer divisions
+/// with a remainder, float results that aren't finite, and `/` with a single argument are kept as calls.
+/// An integer and a float give a float.
+pub fn fold_constants(form: Form) -> Form {
+    let form = match form {
+        form @ Form::Quote(_) => return form,
+        form => form.map_forms(fold_constants),
+    };
+
+    fold_call(&form).unwrap_or(form)
+}
+
+/// Folds a call of an operator on number literals, `None` when it can't be folded.
+fn fold_call(form: &Form) -> Option<Form> {
+    let Form::Call(forms) = form else { return None };
+    let (head, args) = forms.split_first()?;
+    let operator = match head {
+        Form::Symbol(symbol) if symbol.tail.is_empty() && FOLDED_OPERATORS.contains(&symbol.head.as_str()) => symbol.head.as_str(),
+        _ => return None,
+    };
+
+    let integers: Option<Vec<i64>> = args.iter()
+        .map(|arg| match arg {
+            Form::Integer(i) => Some(*i),
+            _ => None,
+        })
+        .collect();
+
+    if let Some(integers) = integers {
+        return fold_integers(operator, &integers).map(Form::Integer);
+    }
+
+    let floats: Option<Vec<f64>> = args.iter()
+        .map(|arg| match arg {
+            Form::Integer(i) => Some(*i as f64),
+            Form::Float(f) => Some(*f),
+            _ => None,
+        })
+        .collect();
+
+    fold_floats(operator, &floats?).filter(|f| f.is_finite()).map(Form::Float)
+}
+
+fn fold_integers(operator: &str, integers: &[i64]) -> Option<i64> {
+    let (&first, rest) = integers.split_first()?;
+
+    match (operator, rest) {
+        ("-", []) => first.checked_neg(),
+        ("/", []) => None,
+        _ => rest.iter().try_fold(first, |acc, &n| match operator {
+            "+" => acc.checked_add(n),
+            "-" => acc.checked_sub(n),
+            "*" => acc.checked_mul(n),
+            _ => acc.checked_rem(n).filter(|&rem| rem == 0).and(acc.checked_div(n)),
+        }),
+    }
+}
+
+fn fold_floats(operator: &str, floats: &[f64]) -> Option<f64> {
+    let (&first, rest) = floats.split_first()?;
+
+    match (operator, rest) {
+        ("-", []) => Some(-first),
+        ("/", []) => None,
+        _ => Some(rest.iter().fold(first, |acc, &n| match operator {
+            "+" => acc + n,
+            "-" => acc - n,
+            "*" => acc * n,
+            _ => acc / n,
+        })),
+    }
+}
+
+/// A lazy pre-order iterator over a form and all its descendants, returned by [`Form::depth_first_iter`].
+pub struct DepthFirst<'a> {
+    stack: Vec<&'a Form>,
+}
+
+impl<'a> DepthFirst<'a> {
+    pub(crate) fn new(form: &'a Form) -> Self {
+        Self { stack: vec![form] }
+    }
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = &'a Form;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let form = self.stack.pop()?;
+
+        self.stack.extend(form.children().into_iter().rev());
+
+        Some(form)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::lex, parser::{parse, Form, FormKind}};
+
+    use std::collections::HashMap;
+
+    use crate::lexer::symbol::Symbol;
+
+    use super::{find_all, fold, fold_constants, rename, replace_all, split_kwargs};
+
+    fn parse_str(source: &str) -> Form {
+        let tokens = lex(source).unwrap();
+        let (form, _) = parse(tokens.into_iter().peekable()).unwrap();
+        form
+    }
+
+    #[test]
+    fn find_all_symbols() {
+        let form = parse_str("(let [x 1 y x] (+ x y {:x x}))");
+        let found = find_all(&form, |form| matches!(form, Form::Symbol(s) if s.head == "x"));
+
+        assert_eq!(found.len(), 4);
+        assert!(found.iter().all(|form| **form == parse_str("x")));
+    }
+
+    #[test]
+    fn replace_all_integers() {
+        let form = parse_str("(+ 1 [2 3] {:a 4} '5 x)");
+        let replaced = replace_all(form, |form| matches!(form, Form::Integer(_)), |_| Form::Integer(0));
+
+        assert_eq!(replaced, parse_str("(+ 0 [0 0] {:a 0} '0 x)"));
+    }
+
+    #[test]
+    fn rename_bound_symbol() {
+        let symbol = |name: &str| Symbol { head: name.to_string(), tail: vec![], slash: false };
+        let renames = HashMap::from([(symbol("x"), symbol("x__1"))]);
+        let form = parse_str("(let [x 1] (+ x (f {:x x} 'x '(g x)) y))");
+
+        assert_eq!(rename(form, &renames), parse_str("(let [x__1 1] (+ x__1 (f {:x x__1} 'x '(g x)) y))"));
+    }
+
+    #[test]
+    fn fold_sum() {
+        let form = parse_str("(+ 1 [2 3] {4 5} (- 6))");
+        let sum = fold(&form, 0, |sum, form| match form {
+            Form::Integer(i) => sum + i,
+            _ => sum,
+        });
+
+        assert_eq!(sum, 21);
+    }
+
+    #[test]
+    fn depth_first_iter() {
+        let form = parse_str("(f [1 (g)] {:k '2})");
+        let expected = ["(f [1 (g)] {:k '2})", "f", "[1 (g)]", "1", "(g)", "g", "{:k '2}", ":k", "'2", "2"]
+            .map(parse_str);
+
+        assert_eq!(form.depth_first_iter().cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(form.depth_first_iter().filter(|form| form.kind() == FormKind::Call).count(), 2);
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        let folded = |source: &str| fold_constants(parse_str(source));
+
+        assert_eq!(folded("(+ 1 2)"), Form::Integer(3));
+        assert_eq!(folded("(* 2 (+ 1 1))"), Form::Integer(4));
+        assert_eq!(folded("(- 5)"), Form::Integer(-5));
+        assert_eq!(folded("(/ 8 2 2)"), Form::Integer(2));
+        assert_eq!(folded("(/ 1.0 4)"), Form::Float(0.25));
+        assert_eq!(folded("[(+ 1 x) {:a (- 3 1)}]"), parse_str("[(+ 1 x) {:a 2}]"));
+
+        for source in ["(+ 1 x)", "'(+ 1 2)", "(/ 7 2)", "(/ 1 0)", "(/ 1.0 0)", "(/ 2)", "(+)", "(max 1 2)", "(a.+ 1 2)", "(+ 9223372036854775807 1)"] {
+            assert_eq!(folded(source), parse_str(source), "folding {}", source);
+        }
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        let call = parse_str("(f a b :x 1 :y [2])");
+        let (positional, kwargs) = split_kwargs(&call).unwrap();
+
+        assert_eq!(positional, vec![&parse_str("a"), &parse_str("b")]);
+        assert_eq!(kwargs, vec![("x", &Form::Integer(1)), ("y", &parse_str("[2]"))]);
+        assert_eq!(split_kwargs(&parse_str("(f)")), Ok((vec![], vec![])));
+        assert_eq!(split_kwargs(&parse_str("(f a :x 1 :x)")).unwrap_err(), "The keyword argument `:x` has no value");
+        assert_eq!(split_kwargs(&parse_str("(f :x 1 b 2)")).unwrap_err(), "Expected a keyword argument, found a symbol");
+        assert!(split_kwargs(&parse_str("[a :x 1]")).is_err());
+    }
+}
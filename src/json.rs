@@ -0,0 +1,90 @@
+//! A quick, lossy conversion from [`Form`] to JSON, for when a full `Serialize` implementation is too much.
+use serde_json::{Map, Number, Value};
+
+use crate::parser::Form;
+
+/// Converts a form into a JSON value.
+///
+/// Atoms become JSON scalars and calls, lists and sets become arrays.
+/// Maps whose keys are all strings or keywords become objects, keyed by the string or keyword name;
+/// any other map becomes an array of `[key, value]` pairs.
+///
+/// The conversion is lossy:
+/// - symbols become strings prefixed with `'`, like `'foo.bar`, and keywords become strings like `:foo`
+/// - chars become one character strings
+/// - complex numbers become strings like `"3.0+4.0i"`
+/// - `NaN` and the infinities become `null`, as does `nil`
+/// - quotes and tags are dropped, leaving the quoted or tagged form
+/// - the string key `"a"` and the keyword key `:a` collide in an object, and the last one wins
+pub fn to_json_value(form: &Form) -> Value {
+    match form {
+        Form::Integer(i) => Value::from(*i),
+        Form::Float(f) => Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+        Form::String(s) => Value::String(s.to_string()),
+        Form::Char(c) => Value::String(c.to_string()),
+        #[cfg(feature = "complex")]
+        Form::Complex { .. } => Value::String(form.to_string()),
+        Form::Symbol(symbol) => Value::String(format!("'{}", symbol)),
+        Form::Keyword(k) => Value::String(k.to_string()),
+        Form::Bool(b) => Value::Bool(*b),
+        Form::Nil => Value::Null,
+        Form::Call(forms) | Form::List(forms) | Form::Set(forms) => Value::Array(forms.iter().map(to_json_value).collect()),
+        Form::Map(entries) => map_to_json(entries),
+        Form::Quote(form) | Form::Tagged { value: form, .. } => to_json_value(form),
+    }
+}
+
+fn map_to_json(entries: &[(Form, Form)]) -> Value {
+    let keys: Option<Vec<&str>> = entries.iter()
+        .map(|(key, _)| match key {
+            Form::String(s) => Some(&**s),
+            Form::Keyword(k) => Some(k.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    match keys {
+        Some(keys) => {
+            let object: Map<String, Value> = keys.into_iter()
+                .zip(entries)
+                .map(|(key, (_, value))| (key.to_string(), to_json_value(value)))
+                .collect();
+
+            Value::Object(object)
+        },
+        None => Value::Array(entries.iter()
+            .map(|(key, value)| Value::Array(vec![to_json_value(key), to_json_value(value)]))
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::parser::parse_str;
+
+    use super::to_json_value;
+
+    fn json_of(source: &str) -> serde_json::Value {
+        to_json_value(&parse_str(source).unwrap()[0])
+    }
+
+    #[test]
+    fn keyword_map() {
+        assert_eq!(
+            json_of("{:name \"rubla\" :tags [foo :bar] :ok true :none nil}"),
+            json!({ "name": "rubla", "tags": ["'foo", ":bar"], "ok": true, "none": null }),
+        );
+    }
+
+    #[test]
+    fn numeric_list() {
+        assert_eq!(json_of("[1 -2 3.5 ##NaN]"), json!([1, -2, 3.5, null]));
+    }
+
+    #[test]
+    fn other_map() {
+        assert_eq!(json_of("{1 :one [2] \\c}"), json!([[1, ":one"], [[2], "c"]]));
+    }
+}
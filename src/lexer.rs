@@ -1,79 +1,459 @@
 //! The module for lexer related functions and types.
-use std::{fmt::{self, Display, Formatter}, iter::Peekable, ops::Index, str::{CharIndices, Chars}};
+use std::{fmt::{self, Display, Formatter}, ops::Range, str::CharIndices};
 
+use keyword::Keyword;
 use symbol::Symbol;
-use token::Token;
+use token::{TextKind, Token};
 
+mod case;
+pub mod keyword;
 pub mod token;
 pub mod symbol;
 
-/// Characters allowed in keywords
-const KEYWORD_CHARS: &'static str = "abcdefghijklmnopqrstuvwxyz0123456789-";
-/// Characters allowed in symbols
-const SYMBOL_CHARS: &'static str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-+*/|<>=!?@#$%";
+/// Characters allowed in keywords by default
+pub const KEYWORD_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789-";
+/// Characters allowed in symbols by default
+pub const SYMBOL_CHARS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-+*/|<>=!?@#$%";
 /// Characters scapable in strings
-const ESCAPABLE_CHARS: &'static str = "\"ntr\\";
-/// Characters that indicate the end of a token
-const TK_END_CHARS: &'static str = " \n\t\r(){}[]\";,";
+const ESCAPABLE_CHARS: &str = "\"ntr\\";
+/// Characters that indicate the end of a token, besides any other whitespace
+const TK_END_CHARS: &str = " \n\t\r(){}[]\";,";
+
+/// Options that change how the lexer reads the source.
+#[derive(Debug, Clone)]
+pub struct LexerConfig {
+    /// Emits `Whitespace` and `Comment` tokens instead of skipping them,
+    /// so the tokens account for every byte of the source. Defaults to `false`.
+    pub lossless: bool,
+    /// Characters allowed in keywords, besides the `/` of a namespace like `:ns/name`. Defaults to [`KEYWORD_CHARS`]: lowercase letters, digits and `-`.
+    /// Use [`SYMBOL_CHARS`] to allow the same characters as symbols, including uppercase.
+    pub keyword_chars: String,
+    /// Characters allowed in symbols. Defaults to [`SYMBOL_CHARS`].
+    pub symbol_chars: String,
+    /// Reads a leading `+` followed by a digit as the sign of a number, so `+5` is `Integer(5)`
+    /// just like `-5` is `Integer(-5)`. When `false`, `+5` is a symbol. A lone `+` is always a symbol.
+    /// Defaults to `true`.
+    pub plus_signed_numbers: bool,
+    /// Reads `,` as the decimal separator of numbers, so `3,14` is `Float(3.14)`. A `.` in a number
+    /// is then an error, and `,` is no longer whitespace, so it can't separate forms. Defaults to `false`.
+    pub decimal_comma: bool,
+    /// Emits a [`Token::Error`] for each error and carries on lexing after it, skipping the offending
+    /// character and the rest of its token, instead of stopping at the first error. Defaults to `false`.
+    pub error_tokens: bool,
+    /// Prefixes starting a line comment where a token could start. They are checked before anything else,
+    /// so a `#` prefix makes `#{` a comment too. Prefixes that are empty or start with whitespace, `,`,
+    /// a bracket or `"` are ignored. Defaults to `;`.
+    pub comment_prefixes: Vec<String>,
+    /// Emits a [`Token::Text`] for each string, keyword, symbol and tag instead of building its text, which is left
+    /// in the source at the span of the token, so that lexing allocates nothing for them. Whitespace and comments
+    /// have empty text when lossless. Defaults to `false`.
+    pub borrowed: bool,
+    /// Counts columns by their display width in a monospace terminal, so a wide character like `漢`
+    /// takes two columns and a combining mark takes none. Defaults to `false`, counting one column per character.
+    #[cfg(feature = "unicode-width")]
+    pub display_columns: bool,
+}
 
 pub struct Lexer<'source> {
     source: &'source str,
+    config: LexerConfig,
+    /// The characters after `current`, indexed from `offset`.
     index: CharIndices<'source>,
+    offset: usize,
     current: char,
     current_index: usize,
     current_line: usize,
     current_column: usize,
+    peeked: Option<Option<(Result<Token, LexError>, Span)>>,
 }
 
+/// A line and column in the source code, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Position {
     pub line: usize,
     pub column: usize,
 }
 
+/// The region of the source code covered by a token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    /// Position of the first character of the token.
+    pub start: Position,
+    /// Byte offsets of the token in the source.
+    pub range: Range<usize>,
+}
+
+/// A change to a source: the bytes in `range` of the old source replaced by `inserted` bytes of new text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub inserted: usize,
+}
+
+/// An error found while lexing, with the position where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub position: Position,
+    /// The name of the file the error was found in, when it's known.
+    pub file: Option<String>,
+}
+
+/// The line ending style used by a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Lines end with `\n`.
+    Lf,
+    /// Lines end with `\r\n`.
+    CrLf,
+    /// Both `\n` and `\r\n` are used.
+    Mixed,
+}
+
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self {
+            lossless: false,
+            keyword_chars: KEYWORD_CHARS.to_string(),
+            symbol_chars: SYMBOL_CHARS.to_string(),
+            plus_signed_numbers: true,
+            decimal_comma: false,
+            error_tokens: false,
+            comment_prefixes: vec![";".to_string()],
+            borrowed: false,
+            #[cfg(feature = "unicode-width")]
+            display_columns: false,
+        }
+    }
+}
+
+/// Lexes the whole source string into a vector of tokens.
+pub fn lex(source: &str) -> Result<Vec<Token>, LexError> {
+    Lexer::new(source).collect()
+}
+
+/// Lexes the whole source string into a vector of tokens along with their spans.
+pub fn lex_spanned(source: &str) -> Result<Vec<(Token, Span)>, LexError> {
+    Lexer::new(source).collect_spanned()
+}
+
+/// Lexes the whole source string into a vector of tokens along with their byte ranges in the source.
+pub fn lex_offsets(source: &str) -> Result<Vec<(Token, Range<usize>)>, LexError> {
+    let tokens = lex_spanned(source)?;
+
+    Ok(tokens.into_iter().map(|(token, span)| (token, span.range)).collect())
+}
+
+/// Lexes `new_source` after an edit, reusing the tokens of [`lex_spanned`] for the old source.
+///
+/// Tokens before the edit are kept, and lexing restarts at the start of the run of tokens with nothing
+/// between them that reaches the edit, since lexing one of them, like the `3` of a complex `3+4i`, may have
+/// looked past its end. Once a token past the edit starts where an old one did, so the rest of the source
+/// is unchanged, the remaining old tokens are reused with their spans shifted. An edit that changes how far
+/// a string or comment reaches is lexed until the sources line up again, or to the end.
+pub fn relex(old_tokens: &[(Token, Span)], edit: &Edit, new_source: &str) -> Result<Vec<(Token, Span)>, LexError> {
+    let mut kept = old_tokens.iter()
+        .take_while(|(_, span)| span.range.end < edit.range.start)
+        .count()
+        .saturating_sub(1);
+
+    while kept > 0 && old_tokens[kept - 1].1.range.end == old_tokens[kept].1.range.start {
+        kept -= 1;
+    }
+
+    let mut tokens = old_tokens[..kept].to_vec();
+    let mut lexer = Lexer::new(new_source);
+
+    if let Some((_, span)) = tokens.last() {
+        lexer.skip_to(span.range.end);
+    }
+
+    let shift = edit.inserted as isize - edit.range.len() as isize;
+    let edit_end = edit.range.start + edit.inserted;
+    let mut old = kept;
+
+    while let Some(next) = lexer.next_spanned() {
+        let (token, span) = next?;
+
+        if span.range.start >= edit_end {
+            let old_start = span.range.start.wrapping_add_signed(-shift);
+
+            while old_tokens.get(old).is_some_and(|(_, old_span)| old_span.range.start < old_start) {
+                old += 1;
+            }
+
+            if let Some((old_token, old_span)) = old_tokens.get(old).filter(|(_, old_span)| old_span.range.start == old_start) {
+                if old_token.semantic_eq(&token) {
+                    let (from, to) = (old_span.start, span.start);
+
+                    tokens.extend(old_tokens[old..].iter().map(|(token, old_span)| {
+                        (token.clone(), shift_span(old_span, from, to, shift))
+                    }));
+
+                    return Ok(tokens);
+                }
+            }
+        }
+
+        tokens.push((token, span));
+    }
+
+    Ok(tokens)
+}
+
+/// Moves a span after an edit, given that the token at `from` moved to `to` and every byte moved by `shift`.
+/// Only tokens on the same line as `from` change column.
+fn shift_span(span: &Span, from: Position, to: Position, shift: isize) -> Span {
+    let column = if span.start.line == from.line {
+        span.start.column + to.column - from.column
+    } else {
+        span.start.column
+    };
+
+    Span {
+        start: Position { line: span.start.line + to.line - from.line, column },
+        range: span.range.start.wrapping_add_signed(shift)..span.range.end.wrapping_add_signed(shift),
+    }
+}
+
+/// Returns the token spanning the byte `offset` of the source along with its byte range, like the symbol
+/// under an editor's cursor. Returns `None` when the offset is in whitespace or past the end, or when
+/// lexing fails before reaching it.
+///
+/// The source is lexed from its start, since the offset may be inside a string spanning lines,
+/// but lexing stops at the token found.
+pub fn lex_one_at(source: &str, offset: usize) -> Option<(Token, Range<usize>)> {
+    let mut lexer = Lexer::with_config(source, LexerConfig { lossless: true, ..Default::default() });
+
+    while let Some(Ok((token, span))) = lexer.next_spanned() {
+        if span.range.end > offset {
+            return match token {
+                Token::Whitespace(_) => None,
+                token => (span.range.start <= offset).then_some((token, span.range)),
+            };
+        }
+    }
+
+    None
+}
+
+/// Merges every run of adjacent whitespace tokens into a single one, keeping the other tokens untouched.
+/// The lexer already reads a run of whitespace as one token, but edited token streams may split it.
+pub fn normalize_trivia(tokens: Vec<Token>) -> Vec<Token> {
+    let mut normalized: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        match (normalized.last_mut(), token) {
+            (Some(Token::Whitespace(last)), Token::Whitespace(s)) => last.push_str(&s),
+            (_, token) => normalized.push(token),
+        }
+    }
+
+    normalized
+}
+
+/// Returns the start of every line whose indentation mixes tabs and spaces, which is likely to misalign.
+///
+/// This needs the whitespace tokens, so `tokens` must come from a lossless lexer, like [`lex_lossless`].
+/// Lines holding only whitespace are skipped.
+pub fn check_indentation(tokens: &[(Token, Span)]) -> Vec<Position> {
+    let mut mixed = Vec::new();
+    let mut indentation = Some(String::new());
+
+    for (token, span) in tokens {
+        match token {
+            Token::Whitespace(s) => {
+                for c in s.chars() {
+                    if c == '\n' {
+                        indentation = Some(String::new());
+                    } else if let Some(indentation) = &mut indentation {
+                        indentation.push(c);
+                    }
+                }
+            },
+            // Lines are taken from the span, as the decoded text of a string doesn't tell how many it spans
+            _ => {
+                if indentation.take().is_some_and(|s| s.contains(' ') && s.contains('\t')) {
+                    mixed.push(Position { line: span.start.line, column: 1 });
+                }
+            },
+        }
+    }
+
+    mixed
+}
+
+/// Detects which line ending the source uses. A source without line breaks is reported as [`LineEnding::Lf`].
+pub fn detect_line_ending(source: &str) -> LineEnding {
+    let crlf = source.matches("\r\n").count();
+    let lf = source.matches('\n').count() - crlf;
+
+    match (lf, crlf) {
+        (_, 0) => LineEnding::Lf,
+        (0, _) => LineEnding::CrLf,
+        _ => LineEnding::Mixed,
+    }
+}
+
+/// Lexes the whole source string keeping whitespace and comments, along with the span of each token.
+pub fn lex_lossless(source: &str) -> Result<Vec<(Token, Span)>, LexError> {
+    Lexer::with_config(source, LexerConfig { lossless: true, ..Default::default() }).collect_spanned()
+}
+
 impl<'source> Lexer<'source> {
     /// Builds a new lexer from a source string.
     pub fn new(source: &'source str) -> Self {
-        let mut index = source.char_indices(); 
-        let (i, c) = index.next().unwrap_or((0, '\0'));
+        Self::with_config(source, LexerConfig::default())
+    }
 
-        Self {
+    /// Builds a new lexer from a source string using the given configuration.
+    /// A leading UTF-8 byte order mark is skipped.
+    pub fn with_config(source: &'source str, config: LexerConfig) -> Self {
+        let mut index = source.char_indices();
+        let (i, c) = index.next().unwrap_or((source.len(), '\0'));
+
+        let mut lexer = Self {
             source, // TODO: Remove \r
+            config,
             current: c,
             current_index: i,
             index,
+            offset: 0,
             current_line: 1,
             current_column: 1,
+            peeked: None,
+        };
+
+        if lexer.current == '\u{FEFF}' {
+            lexer.advance();
+            lexer.current_column = 1;
+        }
+
+        lexer
+    }
+
+    /// Returns the next token without consuming it.
+    /// Peeking several times in a row returns the same token.
+    pub fn peek_token(&mut self) -> Option<&Result<Token, LexError>> {
+        if self.peeked.is_none() {
+            let next = self.lex_token();
+            self.peeked = Some(next);
         }
+
+        self.peeked.as_ref().and_then(|peeked| peeked.as_ref()).map(|(token, _)| token)
+    }
+
+    /// Returns the next token along with its span.
+    pub fn next_spanned(&mut self) -> Option<Result<(Token, Span), LexError>> {
+        let (token, span) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.lex_token(),
+        }?;
+
+        Some(token.map(|token| (token, span)))
+    }
+
+    /// Consumes the rest of the source into tokens along with their spans.
+    pub fn collect_spanned(mut self) -> Result<Vec<(Token, Span)>, LexError> {
+        std::iter::from_fn(|| self.next_spanned()).collect()
     }
 
     /// Advances the lexer to the next character.
+    /// At the end of the input `current` becomes `'\0'` and `None` is returned.
     fn advance(&mut self) -> Option<char> {
-        self.index.next().map(|(i, c)| {
-            if c == '\n' {
-                self.current_line += 1;
-                self.current_column = 1;
-            } else {
-                self.current_column += 1;
-            }
+        if self.is_eof() {
+            return None;
+        }
+
+        if self.current == '\n' {
+            self.current_line += 1;
+            self.current_column = 1;
+        } else {
+            self.current_column += self.width(self.current);
+        }
+
+        match self.index.next() {
+            Some((i, c)) => {
+                self.current = c;
+                self.current_index = self.offset + i;
+
+                Some(c)
+            },
+            None => {
+                self.current = '\0';
+                self.current_index = self.source.len();
+
+                None
+            },
+        }
+    }
+
+    /// Advances the lexer to the character at the byte offset `end`, which must be a char boundary
+    /// at or after the current character. The skipped text is scanned only to keep the position right.
+    fn skip_to(&mut self, end: usize) {
+        let skipped = &self.source[self.current_index..end];
+
+        match memchr::memrchr(b'\n', skipped.as_bytes()) {
+            Some(last) => {
+                self.current_line += memchr::memchr_iter(b'\n', skipped.as_bytes()).count();
+                self.current_column = 1 + self.text_width(&skipped[last + 1..]);
+            },
+            None => self.current_column += self.text_width(skipped),
+        }
+
+        self.index = self.source[end..].char_indices();
+        self.offset = end;
 
-            self.current = c;
-            self.current_index = i;
+        match self.index.next() {
+            Some((_, c)) => {
+                self.current = c;
+                self.current_index = end;
+            },
+            None => {
+                self.current = '\0';
+                self.current_index = self.source.len();
+            },
+        }
+    }
 
-            c
-        })
+    /// Advances the lexer to the line break ending the current line, or to the end of the input.
+    fn skip_line(&mut self) {
+        let end = memchr::memchr(b'\n', self.remaining().as_bytes()).map_or(self.source.len(), |i| self.current_index + i);
+
+        self.skip_to(end);
     }
 
     /// Advances the lexer n characters
     fn advancen(&mut self, n: usize) -> bool {
-        if n == 0 {
-            return true;
+        for _ in 0..n {
+            if self.advance().is_none() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns how many columns `text` takes.
+    fn text_width(&self, text: &str) -> usize {
+        #[cfg(feature = "unicode-width")]
+        if self.config.display_columns {
+            return text.chars().map(|c| self.width(c)).sum();
+        }
+
+        text.chars().count()
+    }
+
+    /// Returns how many columns `c` takes.
+    #[inline]
+    fn width(&self, c: char) -> usize {
+        #[cfg(feature = "unicode-width")]
+        if self.config.display_columns {
+            return unicode_width::UnicodeWidthChar::width(c).unwrap_or(1);
         }
 
-        self.index.nth(n-1).map(|(i, c)| {
-            self.current = c;
-            self.current_index = i;
-            self.current_column += n;
-        }).is_some()
+        let _ = c;
+        1
     }
 
     #[inline]
@@ -85,7 +465,7 @@ impl<'source> Lexer<'source> {
     fn currentn(&self, n: usize) -> &'source str {
         let end = self.index.clone()
             .nth(n-1)
-            .map(|(i, _)| i)
+            .map(|(i, _)| self.offset + i)
             .unwrap_or(self.source.len());
 
         &self.source[self.current_index..end]
@@ -97,266 +477,1627 @@ impl<'source> Lexer<'source> {
         self.index.clone().next().map(|(_, c)| c)
     }
 
+    /// Returns the whole source being lexed.
+    #[inline]
+    pub fn source(&self) -> &'source str {
+        self.source
+    }
+
+    /// Returns the part of the source that wasn't lexed yet.
+    /// A token returned by [`Lexer::peek_token`] counts as already lexed.
+    #[inline]
+    pub fn remaining(&self) -> &'source str {
+        self.source.get(self.current_index..).unwrap_or("")
+    }
+
+    /// Returns whether the whole source was consumed.
+    #[inline]
+    pub fn is_eof(&self) -> bool {
+        self.current_index >= self.source.len()
+    }
+
     /// Returns the position of the lexer in the source code.
     #[inline]
-    fn position(&self) -> Position {
+    fn current_position(&self) -> Position {
         Position {
             line: self.current_line,
             column: self.current_column,
         }
     }
 
-    pub fn lex(&mut self) -> Result<Vec<Token>, String> {
-        let mut tokens = Vec::new();
+    /// Builds an error at the current position.
+    fn error(&self, message: String) -> LexError {
+        self.error_at(self.current_position(), message)
+    }
+
+    /// Builds an error at the given position.
+    fn error_at(&self, position: Position, message: String) -> LexError {
+        LexError {
+            message,
+            position,
+            file: None,
+        }
+    }
 
+    /// Lexes the next token and its span, skipping whitespace and comments unless lossless.
+    /// Returns `None` at the end of the input.
+    fn lex_token(&mut self) -> Option<(Result<Token, LexError>, Span)> {
         loop {
-            match self.current {
-                // Skip whitespace
-                c if c.is_whitespace() => { self.advance(); },
+            // `current` is `'\0'` at the end of the input, so check the index to tell it apart
+            // from a null character in the source
+            if self.is_eof() {
+                return None;
+            }
+
+            let start = self.current_position();
+            let start_index = self.current_index;
+
+            let token = match self.current() {
+                // Parse a comment
+                _ if self.comment_prefix().is_some() => {
+                    let comment = self.lex_comment();
+                    if !self.config.lossless {
+                        continue
+                    }
+                    Ok(comment)
+                },
+                // Parse whitespace
+                c if self.is_whitespace(c) => {
+                    let whitespace = self.lex_whitespace();
+                    if !self.config.lossless {
+                        continue
+                    }
+                    Ok(whitespace)
+                },
                 // Parse a scope start
-                '(' | '{' | '[' => tokens.push(Token::Open(self.current)),
+                c @ ('(' | '{' | '[') => { self.advance(); Ok(Token::Open(c)) },
                 // Parse a scope end
-                ')' | '}' | ']' => tokens.push(Token::Close(self.current)),
-                // Parse a string
-                '"' => {
-                    let string = self.lex_string()?;
-                    tokens.push(string);
+                c @ (')' | '}' | ']') => { self.advance(); Ok(Token::Close(c)) },
+                // Parse a quote
+                '\'' => { self.advance(); Ok(Token::Quote) },
+                // Parse a shebang, only on the very first line
+                '#' if self.current_position() == (Position { line: 1, column: 1 }) && self.peek() == Some('!') => {
+                    let shebang = self.lex_shebang();
+                    if !self.config.lossless {
+                        continue
+                    }
+                    Ok(shebang)
                 },
+                // Parse a set start
+                '#' if self.peek() == Some('{') => { self.advancen(2); Ok(Token::OpenSet) },
+                // Parse a special float value
+                '#' if self.peek() == Some('#') => self.lex_special_float(),
+                // Parse a radix number
+                '#' if self.starts_radix_number() => self.lex_radix_number(),
+                // Parse a discard, either as `#_` or as the Scheme datum comment `#;`
+                '#' if matches!(self.peek(), Some('_' | ';')) => { self.advancen(2); Ok(Token::Discard) },
+                // Parse a reader conditional
+                '#' if self.peek() == Some('?') => { self.advancen(2); Ok(Token::ReaderConditional) },
+                // Parse a tag, like `#inst`, which starts with a letter so that symbols like `#!foo` stay symbols
+                '#' if self.peek().is_some_and(char::is_alphabetic) => self.lex_tag(),
+                // Parse a string
+                '"' => self.lex_string(),
                 // Parse a keyword
-                ':' => {
-                    let kw = self.lex_keyword()?;
-                    tokens.push(kw);
-                },
+                ':' => self.lex_keyword(),
                 // Parse a character
-                '\\' => {
-                    let (ch, rest) = lex_char(chars)?;
-                    tokens.push(ch);
-                    chars = rest;
-                },
-                // Parse a comment
-                ';' => {
-                    while let Some(c) = self.advance() {
-                        if c == '\n' {
-                            self.advance();
-                        }
-                    }
-                },
+                '\\' => self.lex_char(),
+                // Parse a verbatim symbol
+                '|' => self.lex_verbatim_symbol(),
                 // Parse a number
-                c if ((c == '-' || c == '.') && chars.peek().is_some_and(|c| c.is_numeric())) || c.is_numeric() => {
-                    let (number, rest) = lex_number(chars, c)?;
-                    tokens.push(number);
-                    chars = rest;
-                },
+                _ if self.starts_number() => self.lex_number(),
                 // Parse a symbol
-                c if SYMBOL_CHARS.contains(c) => {
-                    let (symbol, rest) = lex_symbol(chars, c)?;
-                    tokens.push(Token::Symbol(symbol));
-                    chars = rest;
-                },
+                c if self.config.symbol_chars.contains(c) || c == '.' => self.lex_symbol_token(),
+                // Error on a null character that is part of the source
+                '\0' => Err(self.error("Unexpected null character".to_string())),
                 // Error on unexpected character
-                _ => return Err(format!("Unexpected character: {}", c)),
+                c => Err(self.error(format!("Unexpected character: {}", c))),
+            };
+
+            let token = match token {
+                Err(err) if self.config.error_tokens => {
+                    // Skip the offending character and the rest of the broken token
+                    if self.current_index == start_index {
+                        self.advance();
+                    }
+                    while !self.is_eof() && !is_token_end(self.current()) {
+                        self.advance();
+                    }
+                    Ok(Token::Error(Box::new(err)))
+                },
+                token => token,
+            };
+
+            let span = Span {
+                start,
+                range: start_index..self.current_index,
             };
-        }    
 
-        Ok(tokens)
+            return Some((token, span));
+        }
+    }
+
+    /// This expects `current` to be a whitespace character. It will consume all the
+    /// consecutive whitespace and return it as a token.
+    /// The lexer will be at the next character after the whitespace.
+    fn lex_whitespace(&mut self) -> Token {
+        let start = self.current_index;
+
+        while self.is_whitespace(self.current()) {
+            if self.advance().is_none() {
+                break;
+            }
+        }
+
+        Token::Whitespace(self.trivia_text(start))
+    }
+
+    /// Returns the text from `start` to the current character for a whitespace or comment token,
+    /// or an empty one when the token is skipped or its text is left in the source.
+    fn trivia_text(&self, start: usize) -> String {
+        if self.config.lossless && !self.config.borrowed {
+            self.source[start..self.current_index].to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Returns the length in bytes of the longest comment prefix starting at the current character, if any.
+    fn comment_prefix(&self) -> Option<usize> {
+        self.config.comment_prefixes.iter()
+            .filter(|prefix| prefix.starts_with(|c: char| !c.is_whitespace() && !"(){}[]\",".contains(c)))
+            .filter(|prefix| self.remaining().starts_with(prefix.as_str()))
+            .map(String::len)
+            .max()
+    }
+
+    /// This expects `current` to be at a comment prefix. It will consume the comment and return it,
+    /// with the number of times the prefix is repeated, like `;;`, as its level.
+    /// The lexer will be at the line break ending the comment, or at the end of the input.
+    fn lex_comment(&mut self) -> Token {
+        let prefix = &self.remaining()[..self.comment_prefix().unwrap_or_default()];
+        let mut level = 0;
+
+        while !prefix.is_empty() && self.remaining().starts_with(prefix) {
+            level += 1;
+            self.advancen(prefix.chars().count());
+        }
+
+        let start = self.current_index;
+
+        self.skip_line();
+
+        Token::Comment {
+            level,
+            text: self.trivia_text(start),
+        }
     }
 
     /// This expects `current` to be `"`. It will consume the string and return a token.
     /// The lexer will be at the next character after the closing `"`.
-    fn lex_string(&mut self) -> Result<Token, String> {
-        let mut string = String::new();
-        //let start = self.position();
+    fn lex_string(&mut self) -> Result<Token, LexError> {
+        trace!("lexing string starting at {}", self.current_position());
+
+        let start = self.current_position();
+
+        self.advance();
+
+        let content_start = self.current_index;
 
         loop {
-            match self.advance() {
-                None => return Err(format!("Unexpected end of input, expected `\"` at {}", self.position())),
-                Some('\\') => match self.advance() {
-                    None => return Err(format!("Unexpected end of input, expected `n`, `t`, `r`, `\\` or `\"` at {}", self.position())),
-                    Some(c) if ESCAPABLE_CHARS.contains(c) => string.push(c),
-                    Some(c) => return Err(format!("Unexpected escape character: {} at {}", c, self.position())),
+            // Everything up to the next `"` or `\` is skipped at once
+            let run = memchr::memchr2(b'"', b'\\', self.remaining().as_bytes()).unwrap_or(self.remaining().len());
+
+            self.skip_to(self.current_index + run);
+
+            match self.current() {
+                '"' => break,
+                '\\' => match self.advance() {
+                    None => return Err(self.error(format!(
+                        "Unexpected end of input in the string `\"{}` opened at {}, expected `n`, `t`, `r`, `\\` or `\"`",
+                        preview(&unescape(&self.source[content_start..])), start,
+                    ))),
+                    // A `\` ending the line continues the string on the next one
+                    Some('\n') => {},
+                    Some('\r') if self.peek() == Some('\n') => { self.advance(); },
+                    Some(c) if ESCAPABLE_CHARS.contains(c) => {},
+                    Some(c) => return Err(self.error(format!("Unexpected escape character: {}", c))),
                 },
-                Some('"') => { self.advance(); break },
-                Some(c) => string.push(c),
+                _ => return Err(self.error(format!(
+                    "Unexpected end of input in the string `\"{}` opened at {}, expected `\"`",
+                    preview(&unescape(&self.source[content_start..])), start,
+                ))),
             }
+
+            self.advance();
         }
 
-        //let end = self.position();
+        let content = &self.source[content_start..self.current_index];
+
+        self.advance();
 
-        Ok(Token::String(string))
+        if self.config.borrowed {
+            return Ok(Token::Text(TextKind::String));
+        }
+
+        Ok(Token::String(unescape(content)))
     }
 
     /// This expects `current` to be `:`. It will consume the keyword and return it.
     /// The lexer will be at the next character after the keyword.
-    fn lex_keyword(&mut self) -> Result<Token, String> {
-        let mut keyword = String::new();
-    
+    fn lex_keyword(&mut self) -> Result<Token, LexError> {
+        trace!("lexing keyword starting at {}", self.current_position());
+
+        let start = self.current_position();
+        let name_start = self.current_index + 1;
+
         loop {
             match self.advance() {
-                Some(c) if KEYWORD_CHARS.contains(c) => {
-                    keyword.push(c);
+                Some(c) if c == '/' || self.config.keyword_chars.contains(c) => {},
+                Some(c) if !is_token_end(c) => return Err(self.error(format!(
+                    "Unexpected character: {} while parsing the keyword `:{}`", c, &self.source[name_start..self.current_index],
+                ))),
+                _ => break,
+            }
+        }
+
+        let keyword = &self.source[name_start..self.current_index];
+
+        Keyword::check_parts(keyword).map_err(|message| self.error_at(start, message))?;
+
+        if self.config.borrowed {
+            return Ok(Token::Text(TextKind::Keyword));
+        }
+
+        Ok(Token::Keyword(Keyword::lexed(keyword.to_string())))
+    }
+
+    /// This expects `current` to be the first character of the symbol, which may be a `.`.
+    /// It will consume the symbol and return its token, reading `true`, `false` and `nil` as literals.
+    /// The lexer will be at the next character after the symbol.
+    fn lex_symbol_token(&mut self) -> Result<Token, LexError> {
+        let start_index = self.current_index;
+        let slash = self.skip_symbol()?;
+
+        match &self.source[start_index..self.current_index] {
+            "true" => Ok(Token::Bool(true)),
+            "false" => Ok(Token::Bool(false)),
+            "nil" => Ok(Token::Nil),
+            _ if self.config.borrowed => Ok(Token::Text(TextKind::Symbol)),
+            text => Ok(Token::Symbol(Box::new(split_symbol(text, slash)))),
+        }
+    }
+
+    /// This expects `current` to be the first character of the symbol, which may be a `.`.
+    /// It will consume the symbol, checking its parts, and return whether a `/` splits its namespace from its name.
+    /// The lexer will be at the next character after the symbol.
+    fn skip_symbol(&mut self) -> Result<bool, LexError> {
+        trace!("lexing symbol starting at {}", self.current_position());
+
+        let start = self.current_position();
+        let start_index = self.current_index;
+        let mut part_start = start_index;
+        let mut last_dot = self.current_position();
+        let mut c = self.current();
+
+        loop {
+            match c {
+                c if self.config.symbol_chars.contains(c) => {},
+                '.' if self.current_index == start_index => {
+                    return Err(self.error("A symbol can't start with a `.`".to_string()));
                 },
-                Some(c) if TK_END_CHARS.contains(c) => {
-                    if keyword.is_empty() {
-                        return Err(format!("Empty keyword at {}", self.position()));
-                    }
-                    break;
+                '.' if self.current_index == part_start => {
+                    return Err(self.error("A symbol can't have an empty part between `.`".to_string()));
                 },
-                Some(c) => return Err(format!("Unexpected character: {} at {} while parsing the keyword `:{}`", c, self.position(), keyword)),
-                None => {
-                    if keyword.is_empty() {
-                        return Err(format!("Empty keyword at {}", self.position()));
-                    }
-                    break;
+                '.' => {
+                    last_dot = self.current_position();
+                    part_start = self.current_index + 1;
                 },
+                _ => break,
+            }
+
+            match self.advance() {
+                Some(next) => c = next,
+                None => break,
             }
         }
 
-        Ok(Token::Keyword(keyword))
-    }
-}
+        if part_start == self.current_index {
+            return Err(self.error_at(last_dot, "A symbol can't end with a `.`".to_string()));
+        }
 
-fn lex_symbol(mut source: Peekable<Chars>, first: char) -> Result<(Symbol, Peekable<Chars>), String> {
-    let mut parts = vec![];
-    let mut current = first.to_string();
+        let symbol = &self.source[start_index..self.current_index];
+        let mut parts = symbol.split('.');
 
-    loop {
-        match source.peek() {
-            Some(&c) if SYMBOL_CHARS.contains(c) => {
-                current.push(c);
-                source.next();
+        // A `/` after the first character splits the namespace from the name, so `/` alone stays a symbol
+        match parts.find(|part| part.contains('/')) {
+            Some(part) if part.starts_with('/') => Ok(false),
+            Some(_) if parts.next().is_some() => {
+                Err(self.error_at(start, format!("The symbol `{}` can't have a `.` after its `/`", symbol)))
             },
-            Some('.') => {
-                parts.push(current);
-                current = String::new();
-                source.next();
-            },
-            _ => {
-                if current.is_empty() {
-                    return Err(format!("A symbol can't end with a `.`"));
+            Some(part) => {
+                let (_, name) = part.split_once('/').unwrap_or_default();
+
+                if name.is_empty() {
+                    return Err(self.error_at(start, format!("The symbol `{}` has an empty name after its `/`", symbol)));
                 }
 
-                parts.push(current);
-                break;
+                if name.contains('/') {
+                    return Err(self.error_at(start, format!("The symbol `{}` can have only one `/`", symbol)));
+                }
+
+                Ok(true)
             },
+            None => Ok(false),
         }
     }
 
-    let head = parts.remove(0);
+    /// This expects `current` to be the `#` of a `#!` at the start of the source.
+    /// It will consume the line as a comment of level 0.
+    /// The lexer will be at the line break ending the line, or at the end of the input.
+    fn lex_shebang(&mut self) -> Token {
+        self.advancen(2);
 
-    Ok((Symbol { head, tail: parts }, source))
-}
+        let start = self.current_index;
 
-fn lex_number(mut source: Peekable<Chars>, first: char) -> Result<(Token, Peekable<Chars>), String> {
-    let mut number = first.to_string();
+        self.skip_line();
 
-    loop {
-        match source.peek() {
-            Some(&c) if c.is_numeric() => {
-                number.push(c);
-                source.next();
-            },
-            Some(&'.') => {
-                number.push('.');
-                source.next();
-            },
-            _ => break,
+        Token::Comment {
+            level: 0,
+            text: self.trivia_text(start),
         }
     }
 
-    if number.chars().filter(|&c| c == '.').count() > 1 {
-        return Err(format!("Invalid number: {}", number));
-    }
+    /// This expects `current` to be `|`. It will consume every character up to the closing `|`
+    /// as a single symbol, without splitting on `.`. A `\|` inserts a literal `|` and a `\\` a literal `\`.
+    /// The lexer will be at the next character after the closing `|`.
+    fn lex_verbatim_symbol(&mut self) -> Result<Token, LexError> {
+        let start = self.current_position();
+        let content_start = self.current_index + 1;
+        let unterminated = |lexer: &Self| lexer.error_at(start, format!(
+            "Unterminated verbatim symbol `|{}`, expected `|`", preview(&unescape_verbatim(&lexer.source[content_start..])),
+        ));
 
-    let tk = if number.contains('.') {
-        Token::Float(number.parse().unwrap())
-    } else {
-        Token::Integer(number.parse().unwrap())
-    };
+        loop {
+            match self.advance() {
+                None => return Err(unterminated(self)),
+                Some('\\') => match self.advance() {
+                    Some('|' | '\\') => {},
+                    Some(c) => return Err(self.error(format!("Unexpected escape character: {} in verbatim symbol", c))),
+                    None => return Err(unterminated(self)),
+                },
+                Some('|') => break,
+                Some(_) => {},
+            }
+        }
 
-    Ok((tk, source))
-}
+        let content = &self.source[content_start..self.current_index];
 
-fn lex_char(mut source: Peekable<Chars>) -> Result<(Token, Peekable<Chars>), String> {
-    let mut ch = String::new();
+        self.advance();
 
-    while let Some(c) = source.next() {
-        if c == ' ' {
-            break;
-        } else {
-            ch.push(c);
+        if self.config.borrowed {
+            return Ok(Token::Text(TextKind::Symbol));
         }
+
+        Ok(Token::Symbol(Box::new(Symbol { head: unescape_verbatim(content), tail: vec![], slash: false })))
     }
 
-    let c = match ch.as_str() {
-        "newline" => '\n',
-        "return" => '\r',
-        "tab" => '\t',
-        "space" => ' ',
-        c if c.len() == 1 => c.chars().next().unwrap(), 
-        _ => return Err(format!("Invalid character: {}", ch)),
-    };
+    /// Returns whether `c` is whitespace, which includes `,` unless it's the decimal separator.
+    fn is_whitespace(&self, c: char) -> bool {
+        c.is_whitespace() || (c == ',' && !self.config.decimal_comma)
+    }
 
-    Ok((Token::Char(c), source))
-}
+    /// Returns the character separating the integer and fractional parts of numbers.
+    fn decimal_separator(&self) -> char {
+        if self.config.decimal_comma { ',' } else { '.' }
+    }
 
-impl Display for Position {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.line, self.column)
+    /// Returns whether a number starts at the current character.
+    /// A number may start with a `-` or `+` sign and/or a `.`, but a digit must follow them,
+    /// so a lone `-` or `+` is still a symbol.
+    fn starts_number(&self) -> bool {
+        let mut chars = self.currentn(3).chars().peekable();
+
+        chars.next_if(|&c| c == '-' || (c == '+' && self.config.plus_signed_numbers));
+        chars.next_if(|&c| c == self.decimal_separator());
+
+        chars.next().is_some_and(|c| c.is_numeric())
     }
-}
 
-#[cfg(test)]
+    /// Returns whether a radix number starts at the current `#`. A base letter must be followed by a sign,
+    /// a decimal digit, the end of the token or only digits of its base, so that `#b101` is a number and
+    /// `#b2` an invalid one, but `#bar` and `#xavier` are tags.
+    fn starts_radix_number(&self) -> bool {
+        let mut chars = self.source[self.current_index..].chars().skip(1);
+
+        let base = match chars.next() {
+            Some(c) if c.is_ascii_digit() => return true,
+            Some('x' | 'X') => 16,
+            Some('o' | 'O') => 8,
+            Some('b' | 'B') => 2,
+            _ => return false,
+        };
+
+        let mut digits = chars.take_while(|&c| !is_token_end(c)).peekable();
+
+        match digits.peek() {
+            Some(&c) if matches!(c, '-' | '+') || c.is_ascii_digit() => true,
+            _ => digits.all(|c| c.is_digit(base)),
+        }
+    }
+
+    /// Returns whether an exponent like `e10` or `E-3` starts at the current character.
+    fn starts_exponent(&self) -> bool {
+        let mut chars = self.currentn(3).chars().peekable();
+
+        if chars.next_if(|&c| c == 'e' || c == 'E').is_none() {
+            return false;
+        }
+
+        chars.next_if(|&c| c == '-' || c == '+');
+        chars.next().is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// This expects `current` to be the first character of the number.
+    /// A number with a `.` or an exponent like `1e10` is a float, which errors when it's too large
+    /// to be finite or too small to be told apart from zero.
+    /// With the `complex` feature, a number followed by a signed imaginary part like `+4i` is a complex number.
+    /// The lexer will be at the next character after the number.
+    fn lex_number(&mut self) -> Result<Token, LexError> {
+        trace!("lexing number starting at {}", self.current_position());
+
+        let start = self.current_position();
+        let separator = self.decimal_separator();
+        let mut number = self.current().to_string().replace(separator, ".");
+        let mut exponent = false;
+
+        loop {
+            match self.advance() {
+                Some(c) if c.is_numeric() => {
+                    number.push(c);
+                },
+                Some(c) if c == separator && !exponent => {
+                    number.push('.');
+                },
+                Some('.') if !exponent => {
+                    return Err(self.error(format!("Unexpected `.` in the number {}, the decimal separator is `,`", number)));
+                },
+                Some(_) if !exponent && self.starts_exponent() => {
+                    exponent = true;
+                    number.push(self.current());
+
+                    if matches!(self.peek(), Some('-' | '+')) {
+                        number.extend(self.advance());
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        if number.chars().filter(|&c| c == '.').count() > 1 {
+            return Err(self.error(format!("Invalid number: {}", number)));
+        }
+
+        #[cfg(feature = "complex")]
+        if let Some(im) = self.lex_imaginary() {
+            let re = number.parse().map_err(|_| self.error_at(start, format!("Invalid number: {}", number)))?;
+
+            return Ok(Token::Complex { re, im });
+        }
+
+        let tk = if number.contains('.') || exponent {
+            let float: f64 = number.parse().map_err(|_| self.error(format!("Invalid number: {}", number)))?;
+            let mantissa = number.split(['e', 'E']).next().unwrap_or_default();
+
+            if float.is_infinite() {
+                return Err(self.error_at(start, format!("Float out of range: {}", number)));
+            }
+
+            if float == 0.0 && mantissa.chars().any(|c| c.is_ascii_digit() && c != '0') {
+                return Err(self.error_at(start, format!("Float too small, it would round to zero: {}", number)));
+            }
+
+            Token::Float(float)
+        } else {
+            Token::Integer(number.parse().map_err(|_| self.error(format!("Invalid number: {}", number)))?)
+        };
+
+        Ok(tk)
+    }
+
+    /// Consumes the imaginary part of a complex number, like `+4i` or `-0.5i`, when it starts at the current
+    /// character and ends the token. Anything else is left alone, so `3+4` and `3-i` aren't complex numbers.
+    #[cfg(feature = "complex")]
+    fn lex_imaginary(&mut self) -> Option<f64> {
+        let separator = self.decimal_separator();
+        let mut chars = self.remaining().chars().peekable();
+        let mut imaginary = chars.next().filter(|&c| c == '+' || c == '-')?.to_string();
+
+        while let Some(c) = chars.next_if(|&c| c.is_ascii_digit() || (c == separator && !imaginary.contains('.'))) {
+            imaginary.push(if c == separator { '.' } else { c });
+        }
+
+        if !imaginary.ends_with(|c: char| c.is_ascii_digit()) || chars.next() != Some('i') {
+            return None;
+        }
+
+        if chars.next().is_some_and(|c| !is_token_end(c)) {
+            return None;
+        }
+
+        let im = imaginary.parse().ok()?;
+        self.advancen(imaginary.chars().count() + 1);
+
+        Some(im)
+    }
+
+    /// This expects `current` to be the `#` of `##`. It will consume one of the special float values
+    /// `##Inf`, `##-Inf` or `##NaN`. The lexer will be at the next character after the value.
+    fn lex_special_float(&mut self) -> Result<Token, LexError> {
+        trace!("lexing special float starting at {}", self.current_position());
+
+        let start = self.current_position();
+        let mut name = String::new();
+
+        self.advancen(2);
+
+        while !self.is_eof() && !is_token_end(self.current()) {
+            name.push(self.current());
+            self.advance();
+        }
+
+        match name.as_str() {
+            "Inf" => Ok(Token::Float(f64::INFINITY)),
+            "-Inf" => Ok(Token::Float(f64::NEG_INFINITY)),
+            "NaN" => Ok(Token::Float(f64::NAN)),
+            _ => Err(self.error_at(start, format!("Unknown special value `##{}`, expected `##Inf`, `##-Inf` or `##NaN`", name))),
+        }
+    }
+
+    /// This expects `current` to be the `#` of a tag like `#inst`, which is followed by a symbol.
+    /// The symbol must be written plainly, like `Display` writes it, so that a tag reads back as itself.
+    /// The lexer will be at the next character after the symbol.
+    fn lex_tag(&mut self) -> Result<Token, LexError> {
+        trace!("lexing tag starting at {}", self.current_position());
+
+        let start = self.current_position();
+
+        self.advance();
+
+        let name_start = self.current_index;
+        let slash = self.skip_symbol()?;
+        let name = &self.source[name_start..self.current_index];
+        let symbol = split_symbol(name, slash);
+
+        if !symbol.is_plain(name) {
+            return Err(self.error_at(start, format!("Invalid tag `#{}`, expected a plain symbol after the `#`", name)));
+        }
+
+        if self.config.borrowed {
+            return Ok(Token::Text(TextKind::Tag));
+        }
+
+        Ok(Token::Tag(Box::new(symbol)))
+    }
+
+    /// This expects `current` to be the `#` of a radix number: `#x`, `#o` or `#b` for bases 16, 8 and 2,
+    /// or `#Nr` for any base `N` from 2 to 36, followed by an optionally signed integer in that base.
+    /// The lexer will be at the next character after the number.
+    fn lex_radix_number(&mut self) -> Result<Token, LexError> {
+        trace!("lexing radix number starting at {}", self.current_position());
+
+        let start = self.current_position();
+
+        let base = match self.advance() {
+            Some('x' | 'X') => { self.advance(); 16 },
+            Some('o' | 'O') => { self.advance(); 8 },
+            Some('b' | 'B') => { self.advance(); 2 },
+            _ => {
+                let mut base = String::new();
+
+                while self.current().is_ascii_digit() {
+                    base.push(self.current());
+                    self.advance();
+                }
+
+                if !matches!(self.current(), 'r' | 'R') || self.is_eof() {
+                    return Err(self.error(format!("Expected `r` after the base `#{}`", base)));
+                }
+
+                self.advance();
+
+                match base.parse::<u32>() {
+                    Ok(base) if (2..=36).contains(&base) => base,
+                    _ => return Err(self.error_at(start, format!("Invalid base {}, expected a base from 2 to 36", base))),
+                }
+            },
+        };
+
+        let mut number = String::new();
+
+        if matches!(self.current(), '-' | '+') {
+            number.push(self.current());
+            self.advance();
+        }
+
+        while !self.is_eof() && !is_token_end(self.current()) {
+            let c = self.current();
+
+            if c.to_digit(base).is_none() {
+                return Err(self.error(format!("Invalid digit `{}` for base {}", c, base)));
+            }
+
+            number.push(c);
+            self.advance();
+        }
+
+        if number.is_empty() || number == "-" || number == "+" {
+            return Err(self.error(format!("Expected digits of base {}", base)));
+        }
+
+        i64::from_str_radix(&number, base)
+            .map(Token::Integer)
+            .map_err(|_| self.error_at(start, format!("Number out of range: {} in base {}", number, base)))
+    }
+
+    /// This expects `current` to be `\`. It will consume the character and return it.
+    /// A bracket, `"`, `;` or `,` right after the `\` is the character itself, so `\(` is `(`.
+    /// The lexer will be at the next character after the character literal.
+    fn lex_char(&mut self) -> Result<Token, LexError> {
+        trace!("lexing char starting at {}", self.current_position());
+
+        let mut ch = String::new();
+
+        match self.advance() {
+            Some(c) if c.is_whitespace() => return Err(self.error("Expected a character after `\\`".to_string())),
+            Some(c) if is_token_end(c) => {
+                self.advance();
+                return Ok(Token::Char(c));
+            },
+            Some(c) => ch.push(c),
+            None => return Err(self.error("Expected a character after `\\`".to_string())),
+        }
+
+        while let Some(c) = self.advance() {
+            if is_token_end(c) {
+                break;
+            } else {
+                ch.push(c);
+            }
+        }
+
+        let c = match ch.as_str() {
+            "newline" => '\n',
+            "return" => '\r',
+            "tab" => '\t',
+            "space" => ' ',
+            c if c.chars().count() == 1 => c.chars().next().unwrap(),
+            _ => return Err(self.error(format!("Invalid character: {}", ch))),
+        };
+
+        Ok(Token::Char(c))
+    }
+}
+
+/// Returns whether `c` ends a token: any whitespace, including Unicode spaces like NBSP, or a delimiter.
+fn is_token_end(c: char) -> bool {
+    c.is_whitespace() || TK_END_CHARS.contains(c)
+}
+
+/// Returns the first characters of an unterminated token's content, to point at it in errors.
+fn preview(content: &str) -> String {
+    const PREVIEW_CHARS: usize = 12;
+
+    match content.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}...", &content[..end]),
+        None => content.to_string(),
+    }
+}
+
+/// Splits the text of a lexed symbol into its parts, with the last one after the `/` when `slash` is set.
+fn split_symbol(text: &str, slash: bool) -> Symbol {
+    let (dotted, name) = match text.split_once('/') {
+        Some((dotted, name)) if slash => (dotted, Some(name)),
+        _ => (text, None),
+    };
+    let mut parts = dotted.split('.').chain(name).map(str::to_string);
+
+    Symbol { head: parts.next().unwrap_or_default(), tail: parts.collect(), slash }
+}
+
+/// Decodes the escapes in the content of a string literal, which the lexer already checked.
+/// A `\` ending the content is dropped, so that the content of an unterminated string can be previewed.
+fn unescape(content: &str) -> String {
+    let mut string = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(i) = memchr::memchr(b'\\', rest.as_bytes()) {
+        string.push_str(&rest[..i]);
+
+        let mut chars = rest[i + 1..].chars();
+
+        match chars.next() {
+            Some('n') => string.push('\n'),
+            Some('t') => string.push('\t'),
+            Some('r') => string.push('\r'),
+            Some('\r') if chars.as_str().starts_with('\n') => { chars.next(); },
+            Some('\n') | None => {},
+            Some(c) => string.push(c),
+        }
+
+        rest = chars.as_str();
+    }
+
+    string.push_str(rest);
+
+    string
+}
+
+/// Decodes the `\|` and `\\` escapes in the content of a verbatim symbol, dropping a `\` ending it.
+fn unescape_verbatim(content: &str) -> String {
+    let mut head = String::with_capacity(content.len());
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => head.extend(chars.next()),
+            c => head.push(c),
+        }
+    }
+
+    head
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|token| token.map(|(token, _)| token))
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Renders `message` followed by the line of `source` at `position`, with a caret under its column.
+pub fn render_snippet(source: &str, position: Position, message: &str) -> String {
+    render_snippet_in(source, None, position, message)
+}
+
+/// The ANSI escapes used to render errors, which are all empty for plain output.
+pub(crate) struct Style {
+    error: &'static str,
+    message: &'static str,
+    caret: &'static str,
+    reset: &'static str,
+}
+
+impl Style {
+    pub(crate) const PLAIN: Style = Style { error: "", message: "", caret: "", reset: "" };
+    /// A bold red `error`, a bold message and a red caret.
+    #[cfg(feature = "colored")]
+    pub(crate) const COLORED: Style = Style { error: "\x1b[1;31m", message: "\x1b[1m", caret: "\x1b[31m", reset: "\x1b[0m" };
+
+    /// Formats the `error: message` line starting a rendered error.
+    pub(crate) fn header(&self, message: &str) -> String {
+        format!("{}error{}{}: {}{}", self.error, self.reset, self.message, message, self.reset)
+    }
+}
+
+/// Like [`render_snippet`], but names the file in the header when it's known.
+pub(crate) fn render_snippet_in(source: &str, file: Option<&str>, position: Position, message: &str) -> String {
+    render_styled(source, file, position, message, &Style::PLAIN)
+}
+
+/// Like [`render_snippet_in`], with the header and the caret in the given style.
+pub(crate) fn render_styled(source: &str, file: Option<&str>, position: Position, message: &str, style: &Style) -> String {
+    let location = location(file, position);
+    let line = source.lines().nth(position.line - 1).unwrap_or("");
+    let number = position.line.to_string();
+    let gutter = " ".repeat(number.len());
+    // Keep tabs so the caret lines up with the text above it
+    let padding = line.chars()
+        .take(position.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    let header = style.header(message);
+    let (caret, reset) = (style.caret, style.reset);
+
+    format!("{header}\n{gutter}--> {location}\n{gutter} |\n{number} | {line}\n{gutter} | {padding}{caret}^{reset}")
+}
+
+/// Formats a position as `file:line:column`, or `line:column` when the file isn't known.
+pub(crate) fn location(file: Option<&str>, position: Position) -> String {
+    match file {
+        Some(file) => format!("{}:{}", file, position),
+        None => position.to_string(),
+    }
+}
+
+impl LexError {
+    /// Renders the error along with the line of `source` where it happened.
+    pub fn render(&self, source: &str) -> String {
+        render_snippet_in(source, self.file.as_deref(), self.position, &self.message)
+    }
+
+    /// Like [`LexError::render`], with ANSI colors for a terminal.
+    #[cfg(feature = "colored")]
+    pub fn render_colored(&self, source: &str) -> String {
+        render_styled(source, self.file.as_deref(), self.position, &self.message, &Style::COLORED)
+    }
+
+    /// Attaches the name of the file the error was found in.
+    pub fn in_file(self, file: &str) -> Self {
+        LexError { file: Some(file.to_string()), ..self }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, location(self.file.as_deref(), self.position))
+    }
+}
+
+#[cfg(test)]
 mod tests {
+    use super::{check_indentation, detect_line_ending, lex, relex, Edit, LexError, Span, lex_lossless, lex_offsets, lex_one_at, lex_spanned, normalize_trivia, Keyword, Lexer, LexerConfig, LineEnding, Position, Symbol, TextKind, Token, SYMBOL_CHARS};
+
+    fn pos(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
     #[test]
     fn parse_int_number() {
         let sources = vec![
-            ("", '0', 0, ""),
-            ("23", '1', 123, ""),
-            ("21 foo", '3', 321, " foo"),
-            ("123", '-', -123, ""),
+            ("0", 0, ""),
+            ("123", 123, ""),
+            ("321 foo", 321, " foo"),
+            ("-123", -123, ""),
         ];
 
-        for (source, first, expected, rest) in sources {
-            let (token, rest_iter) = super::lex_number(source.chars().peekable(), first).unwrap();
-            assert_eq!(token, super::Token::Integer(expected));
-            assert_eq!(rest_iter.collect::<String>(), rest);
+        for (source, expected, rest) in sources {
+            let mut lexer = Lexer::new(source);
+            let token = lexer.lex_number().unwrap();
+            assert_eq!(token, Token::Integer(expected));
+            assert_eq!(&lexer.source[lexer.current_index..], rest);
         }
     }
 
     #[test]
     fn parse_float_number() {
         let sources = vec![
-            (".0", '0', 0.0, ""),
-            ("23.", '1', 123.0, ""),
-            ("21.foo", '3', 321.0, "foo"),
-            ("123.456", '-', -123.456, ""),
-            ("123bar", '.', 0.123, "bar"),
+            ("0.0", 0.0, ""),
+            ("123.", 123.0, ""),
+            ("321.foo", 321.0, "foo"),
+            ("-123.456", -123.456, ""),
+            (".123bar", 0.123, "bar"),
+            ("1e3", 1000.0, ""),
+            ("1.5E-2", 0.015, ""),
+            ("-2e+2", -200.0, ""),
+            ("1.0e-x", 1.0, "e-x"),
+        ];
+
+        for (source, expected, rest) in sources {
+            let mut lexer = Lexer::new(source);
+            let token = lexer.lex_number().unwrap();
+            assert_eq!(token, Token::Float(expected));
+            assert_eq!(&lexer.source[lexer.current_index..], rest);
+        }
+    }
+
+    #[test]
+    fn signed_number() {
+        let sources = vec![
+            ("-5", Token::Integer(-5)),
+            ("+5", Token::Integer(5)),
+            ("-1.5", Token::Float(-1.5)),
+            ("+.5", Token::Float(0.5)),
+            ("-", Token::Symbol(Box::new(Symbol { head: "-".to_string(), tail: vec![], slash: false }))),
+            ("+", Token::Symbol(Box::new(Symbol { head: "+".to_string(), tail: vec![], slash: false }))),
+        ];
+
+        for (source, expected) in sources {
+            assert_eq!(lex(source).unwrap(), vec![expected]);
+        }
+
+        let tokens = lex("(- 5 -5)").unwrap();
+        assert_eq!(tokens[1], Token::Symbol(Box::new(Symbol { head: "-".to_string(), tail: vec![], slash: false })));
+        assert_eq!(tokens[2], Token::Integer(5));
+        assert_eq!(tokens[3], Token::Integer(-5));
+    }
+
+    #[test]
+    fn bool_and_nil() {
+        let tokens = lex("true false nil nil.x truthy").unwrap();
+
+        assert_eq!(tokens[0], Token::Bool(true));
+        assert_eq!(tokens[1], Token::Bool(false));
+        assert_eq!(tokens[2], Token::Nil);
+        assert_eq!(tokens[3], Token::Symbol(Box::new(Symbol { head: "nil".to_string(), tail: vec!["x".to_string()], slash: false })));
+        assert_eq!(tokens[4], Token::Symbol(Box::new(Symbol { head: "truthy".to_string(), tail: vec![], slash: false })));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let err = lex("(print\n  \"abc").unwrap_err();
+        assert_eq!(err.message, "Unexpected end of input in the string `\"abc` opened at 2:3, expected `\"`");
+        assert_eq!(err.position, pos(2, 7));
+
+        let err = lex("\"a very long string that never ends").unwrap_err();
+        assert_eq!(err.message, "Unexpected end of input in the string `\"a very long ...` opened at 1:1, expected `\"`");
+    }
+
+    #[test]
+    fn string_line_continuation() {
+        assert_eq!(lex("\"foo\\\nbar\"").unwrap(), vec![Token::String("foobar".to_string())]);
+        assert_eq!(lex("\"foo\\\r\nbar\"").unwrap(), vec![Token::String("foobar".to_string())]);
+        assert_eq!(lex("\"foo\nbar\"").unwrap(), vec![Token::String("foo\nbar".to_string())]);
+        assert_eq!(lex("\"foo\\nbar\"").unwrap(), vec![Token::String("foo\nbar".to_string())]);
+    }
+
+    #[test]
+    #[cfg(feature = "complex")]
+    fn complex() {
+        assert_eq!(lex("3+4i").unwrap(), vec![Token::Complex { re: 3.0, im: 4.0 }]);
+        assert_eq!(lex("0-1i").unwrap(), vec![Token::Complex { re: 0.0, im: -1.0 }]);
+        assert_eq!(lex("(f -1.5+0.5i)").unwrap()[2], Token::Complex { re: -1.5, im: 0.5 });
+        assert_eq!(lex("3+4").unwrap(), vec![Token::Integer(3), Token::Integer(4)]);
+        assert_eq!(lex("3-i").unwrap(), lex("3 -i").unwrap());
+        assert_eq!(lex("3+4ix").unwrap()[0], Token::Integer(3));
+
+        let token = Token::Complex { re: 0.0, im: -1.0 };
+        assert_eq!(token.to_string(), "0.0-1.0i");
+        assert_eq!(lex(&token.to_string()).unwrap(), vec![token]);
+    }
+
+    #[test]
+    fn scanned_positions() {
+        let source = "#!/bin/rubla 漢\n;; 漢字 comment\n(f \"multi\nline 漢\" \"a\\tb\\\"c\\\n  d\" ; trailing\n x \"\")";
+        let tokens = lex_lossless(source).unwrap();
+        // The position of a byte offset, counting one character at a time
+        let scalar = |offset: usize| {
+            let before = &source[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+            pos(before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+        };
+
+        for (_, span) in &tokens {
+            assert_eq!(span.start, scalar(span.range.start), "position of {:?}", &source[span.range.clone()]);
+        }
+
+        let strings: Vec<_> = tokens.into_iter()
+            .filter_map(|(token, _)| match token {
+                Token::String(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(strings, vec!["multi\nline 漢", "a\tb\"c  d", ""]);
+
+        let err = lex("(f \"ab\nc\\\"d").unwrap_err();
+        assert_eq!(err.message, "Unexpected end of input in the string `\"ab\nc\"d` opened at 1:4, expected `\"`");
+        assert_eq!(err.position, pos(2, 5));
+    }
+
+    /// Applies the edit to `old` with [`relex`], checking the result against lexing the new source from scratch.
+    fn check_relex(old: &str, range: std::ops::Range<usize>, text: &str) -> Result<Vec<(Token, Span)>, LexError> {
+        let new = format!("{}{}{}", &old[..range.start], text, &old[range.end..]);
+        let edit = Edit { range, inserted: text.len() };
+        let relexed = relex(&lex_spanned(old).unwrap(), &edit, &new);
+
+        assert_eq!(relexed, lex_spanned(&new), "editing {:?} into {:?}", old, new);
+        relexed
+    }
+
+    #[test]
+    fn relex_edits() {
+        let old = "(foo bar baz)\n; note\n[1 \"s\"]";
+
+        let tokens = check_relex(old, 6..7, "o").unwrap();
+        assert_eq!(tokens[2].0, lex("bor").unwrap()[0]);
+
+        let tokens = check_relex(old, 4..5, "").unwrap();
+        assert_eq!(tokens[1].0, lex("foobar").unwrap()[0]);
+        assert_eq!(tokens[4].1.start, pos(3, 1));
+
+        let err = check_relex(old, 5..5, "\"").unwrap_err();
+        assert!(err.message.starts_with("Unexpected end of input in the string"));
+
+        check_relex(old, 13..13, "\n\n  ").unwrap();
+        check_relex(old, 0..13, "").unwrap();
+    }
+
+    #[test]
+    fn relex_every_edit() {
+        for old in ["(f 1 \"a b\" ; c\n  [x.y :k] #_2e3)", "(g 3+4ix)"] {
+            for i in 0..=old.len() {
+                for text in ["x", " ", "\"", "\n", ";", ")", "1", "i", "+"] {
+                    let _ = check_relex(old, i..i, text);
+                }
+
+                if i < old.len() {
+                    let _ = check_relex(old, i..i + 1, "");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn token_at_offset() {
+        let source = "(print  math.sqrt ; root\n \"a b\")";
+        let symbol = lex("math.sqrt").unwrap().remove(0);
+
+        assert_eq!(lex_one_at(source, 12), Some((symbol.clone(), 8..17)));
+        assert_eq!(lex_one_at(source, 8), Some((symbol, 8..17)));
+        assert_eq!(lex_one_at(source, 0), Some((Token::Open('('), 0..1)));
+        assert_eq!(lex_one_at(source, 30), Some((Token::String("a b".to_string()), 26..31)));
+        assert!(matches!(lex_one_at(source, 20), Some((Token::Comment { .. }, _))));
+        assert_eq!(lex_one_at(source, 7), None);
+        assert_eq!(lex_one_at(source, 25), None);
+        assert_eq!(lex_one_at(source, source.len()), None);
+        assert_eq!(lex_one_at("(a ~ b)", 5), None);
+    }
+
+    #[test]
+    fn mixed_indentation() {
+        let source = "(defn f [x]\n  \t(g x)\n\t\t(h \"a\nb\")\n \t\n\t ; comment\n    x)";
+        assert_eq!(check_indentation(&lex_lossless(source).unwrap()), vec![pos(2, 1), pos(6, 1)]);
+        assert!(check_indentation(&lex_spanned("(a\n \tb)").unwrap()).is_empty());
+
+        // Lines are counted in the source, not in the decoded strings
+        assert_eq!(check_indentation(&lex_lossless("(f \"a\\nb\")\n \t(g)").unwrap()), vec![pos(2, 1)]);
+        assert_eq!(check_indentation(&lex_lossless("(f \"a\\\n b\")\n \t(g)").unwrap()), vec![pos(3, 1)]);
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-width")]
+    fn display_columns() {
+        let source = "(\"漢字\" \"e\u{301}\" x)";
+        let columns = |display_columns: bool| -> Vec<usize> {
+            let config = LexerConfig { display_columns, ..Default::default() };
+
+            Lexer::with_config(source, config).collect_spanned().unwrap()
+                .into_iter()
+                .map(|(_, span)| span.start.column)
+                .collect()
+        };
+
+        assert_eq!(columns(false), vec![1, 2, 7, 12, 13]);
+        assert_eq!(columns(true), vec![1, 2, 9, 13, 14]);
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let lex_with = |source: &str| {
+            let config = LexerConfig { decimal_comma: true, ..Default::default() };
+
+            Lexer::with_config(source, config).collect::<Result<Vec<_>, _>>()
+        };
+
+        assert_eq!(lex_with("(f 2,75 -0,5 1,5e2 ,25 7)"), Ok(vec![
+            Token::Open('('),
+            lex("f").unwrap()[0].clone(),
+            Token::Float(2.75),
+            Token::Float(-0.5),
+            Token::Float(150.0),
+            Token::Float(0.25),
+            Token::Integer(7),
+            Token::Close(')'),
+        ]));
+
+        let err = lex_with("3.14").unwrap_err();
+        assert_eq!(err.message, "Unexpected `.` in the number 3, the decimal separator is `,`");
+        assert_eq!(err.position, pos(1, 2));
+
+        assert_eq!(lex_with("[1 ,]").unwrap_err().message, "Unexpected character: ,");
+        assert_eq!(lex("3,14").unwrap(), vec![Token::Integer(3), Token::Integer(14)]);
+    }
+
+    #[test]
+    fn float_range() {
+        assert_eq!(lex("1e").unwrap(), vec![Token::Integer(1), lex("e").unwrap()[0].clone()]);
+        assert_eq!(lex("0e400").unwrap(), vec![Token::Float(0.0)]);
+        assert_eq!(lex("4e-320").unwrap(), vec![Token::Float(4e-320)]);
+
+        let err = lex("(f 1e400)").unwrap_err();
+        assert_eq!(err.message, "Float out of range: 1e400");
+        assert_eq!(err.position, pos(1, 4));
+
+        let err = lex("-1e-400").unwrap_err();
+        assert_eq!(err.message, "Float too small, it would round to zero: -1e-400");
+        assert_eq!(err.position, pos(1, 1));
+
+        assert_eq!(lex(&format!("{}.0", "9".repeat(400))).unwrap_err().message, format!("Float out of range: {}.0", "9".repeat(400)));
+    }
+
+    #[test]
+    fn special_float() {
+        assert_eq!(lex("##Inf").unwrap(), vec![Token::Float(f64::INFINITY)]);
+        assert_eq!(lex("[##-Inf]").unwrap()[1], Token::Float(f64::NEG_INFINITY));
+        assert!(lex("##NaN").unwrap()[0].semantic_eq(&Token::Float(f64::NAN)));
+
+        for value in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            let token = Token::Float(value);
+            assert!(lex(&token.to_string()).unwrap()[0].semantic_eq(&token));
+        }
+
+        let err = lex("(f ##foo)").unwrap_err();
+        assert_eq!(err.message, "Unknown special value `##foo`, expected `##Inf`, `##-Inf` or `##NaN`");
+        assert_eq!(err.position, pos(1, 4));
+        assert_eq!(lex("##").unwrap_err().message, "Unknown special value `##`, expected `##Inf`, `##-Inf` or `##NaN`");
+    }
+
+    #[test]
+    fn structural_chars() {
+        for c in ['(', ')', '[', ']', '{', '}', '"', ';', ',', '\\'] {
+            let source = format!("(f \\{})", c);
+            let tokens = lex(&source).unwrap();
+
+            assert_eq!(tokens[2], Token::Char(c), "lexing `{}`", source);
+            assert_eq!(tokens.len(), 4, "lexing `{}`", source);
+        }
+
+        assert_eq!(lex("\\((").unwrap(), vec![Token::Char('('), Token::Open('(')]);
+        assert_eq!(lex("\\a").unwrap(), vec![Token::Char('a')]);
+        assert_eq!(lex("\\space").unwrap(), vec![Token::Char(' ')]);
+        assert_eq!(lex("\\").unwrap_err().message, "Expected a character after `\\`");
+        assert_eq!(lex("\\ a").unwrap_err().message, "Expected a character after `\\`");
+    }
+
+    #[test]
+    fn radix_number() {
+        let cases = vec![
+            ("#xFF", 255),
+            ("#xff", 255),
+            ("#b1010", 10),
+            ("#o17", 15),
+            ("#36rZZ", 1295),
+            ("#2r-101", -5),
+            ("#x+1", 1),
         ];
 
-        for (source, first, expected, rest) in sources {
-            let (token, rest_iter) = super::lex_number(source.chars().peekable(), first).unwrap();
-            assert_eq!(token, super::Token::Float(expected));
-            assert_eq!(rest_iter.collect::<String>(), rest);
+        for (source, expected) in cases {
+            assert_eq!(lex(source).unwrap(), vec![Token::Integer(expected)], "lexing `{}`", source);
+        }
+
+        assert_eq!(lex("(+ #x10 1)").unwrap()[2], Token::Integer(16));
+
+        let errors = vec![
+            ("#b102", "Invalid digit `2` for base 2", pos(1, 5)),
+            ("#b2", "Invalid digit `2` for base 2", pos(1, 3)),
+            ("#37r1", "Invalid base 37, expected a base from 2 to 36", pos(1, 1)),
+            ("#1r1", "Invalid base 1, expected a base from 2 to 36", pos(1, 1)),
+            ("#16", "Expected `r` after the base `#16`", pos(1, 4)),
+            ("#x", "Expected digits of base 16", pos(1, 3)),
+            ("#x-)", "Expected digits of base 16", pos(1, 4)),
+            ("#x8000000000000000", "Number out of range: 8000000000000000 in base 16", pos(1, 1)),
+        ];
+
+        for (source, message, position) in errors {
+            let err = lex(source).unwrap_err();
+            assert_eq!(err.message, message, "lexing `{}`", source);
+            assert_eq!(err.position, position, "lexing `{}`", source);
+        }
+    }
+
+    #[test]
+    fn tag() {
+        let tag = |name: &str| Token::Tag(Box::new(Symbol { head: name.to_string(), tail: vec![], slash: false }));
+
+        assert_eq!(lex("#inst \"2024\"").unwrap(), vec![tag("inst"), Token::String("2024".to_string())]);
+        assert_eq!(lex("#bar #xml #obj #b1").unwrap(), vec![tag("bar"), tag("xml"), tag("obj"), Token::Integer(1)]);
+        assert_eq!(lex("#xavier foo").unwrap(), vec![tag("xavier"), lex("foo").unwrap()[0].clone()]);
+        assert_eq!(lex("#xfade").unwrap(), vec![Token::Integer(0xfade)]);
+        assert_eq!(lex("#{").unwrap(), vec![Token::OpenSet]);
+        assert_eq!(lex("#inst").unwrap()[0].to_string(), "#inst");
+        assert_eq!(lex("#my.ns/tag").unwrap()[0].to_string(), "#my.ns/tag");
+
+        for source in ["#nil x", "#true x"] {
+            assert!(lex(source).unwrap_err().message.starts_with("Invalid tag"), "lexing `{}`", source);
+        }
+
+        for source in ["#1 x", "#3+4i x"] {
+            assert!(lex(source).is_err(), "lexing `{}`", source);
+        }
+    }
+
+    #[test]
+    fn verbatim_symbol() {
+        let symbol = |head: &str| Token::Symbol(Box::new(Symbol { head: head.to_string(), tail: vec![], slash: false }));
+
+        assert_eq!(lex("|a b c|").unwrap(), vec![symbol("a b c")]);
+        assert_eq!(lex("(|(a).b| c)").unwrap()[1], symbol("(a).b"));
+        assert_eq!(lex(r"|a\|b\\|").unwrap(), vec![symbol(r"a|b\")]);
+        assert_eq!(lex("|true|").unwrap(), vec![symbol("true")]);
+        assert_eq!(lex("a|b").unwrap(), vec![symbol("a|b")]);
+
+        let err = lex("(foo |a b)").unwrap_err();
+        assert_eq!(err.message, "Unterminated verbatim symbol `|a b)`, expected `|`");
+        assert_eq!(err.position, pos(1, 6));
+    }
+
+    #[test]
+    fn discard() {
+        let tokens = lex("#_a #_#_ b").unwrap();
+
+        assert_eq!(tokens[0], Token::Discard);
+        assert_eq!(tokens[2], Token::Discard);
+        assert_eq!(tokens[3], Token::Discard);
+        assert_eq!(tokens.len(), 5);
+
+        let tokens = lex("#;a #_#; b ;#;").unwrap();
+
+        assert_eq!(tokens, lex("#_a #_#_ b").unwrap());
+    }
+
+    #[test]
+    fn reader_conditional() {
+        let tokens = lex("#?(:a 1)").unwrap();
+
+        assert_eq!(tokens[0], Token::ReaderConditional);
+        assert_eq!(tokens[1], Token::Open('('));
+    }
+
+    #[test]
+    fn offsets() {
+        let source = "foo \"bár\" 1.5";
+        let tokens = lex_offsets(source).unwrap();
+
+        assert_eq!(tokens.iter().map(|(_, range)| range.clone()).collect::<Vec<_>>(), vec![0..3, 4..10, 11..14]);
+        assert_eq!(&source[tokens[0].1.clone()], "foo");
+        assert_eq!(&source[tokens[1].1.clone()], "\"bár\"");
+        assert_eq!(&source[tokens[2].1.clone()], "1.5");
+        assert_eq!(tokens[1].0, Token::String("bár".to_string()));
+    }
+
+    #[test]
+    fn lossless() {
+        let source = "(foo  ; a comment\n\t[1, 2]) ; end";
+        let tokens = lex_lossless(source).unwrap();
+
+        let text = tokens.iter()
+            .map(|(_, span)| &source[span.range.clone()])
+            .collect::<String>();
+
+        assert_eq!(text, source);
+        assert_eq!(tokens[2], (Token::Whitespace("  ".to_string()), super::Span { start: pos(1, 5), range: 4..6 }));
+        assert_eq!(tokens[3].0, Token::Comment { level: 1, text: " a comment".to_string() });
+        assert_eq!(tokens[4].0, Token::Whitespace("\n\t".to_string()));
+        assert_eq!(tokens.last().unwrap().0, Token::Comment { level: 1, text: " end".to_string() });
+        assert_eq!(lex(source).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn normalize_trivia_merges_whitespace() {
+        let tokens = lex_lossless("a   b").unwrap().into_iter().map(|(token, _)| token).collect::<Vec<_>>();
+        let a = tokens[0].clone();
+        let b = tokens[2].clone();
+
+        assert_eq!(normalize_trivia(tokens.clone()), tokens);
+        assert_eq!(tokens[1], Token::Whitespace("   ".to_string()));
+
+        let whitespace = |s: &str| Token::Whitespace(s.to_string());
+        let comment = Token::Comment { level: 1, text: " c".to_string() };
+        let tokens = vec![a.clone(), whitespace(" "), whitespace("  "), comment.clone(), whitespace("\n"), whitespace("\t"), b.clone()];
+
+        assert_eq!(normalize_trivia(tokens), vec![a, whitespace("   "), comment, whitespace("\n\t"), b]);
+    }
+
+    #[test]
+    fn borrowed() {
+        let source = "(f \"a\\tb\" :k/v x.y |v w| #tag nil 1) ; c";
+        let config = LexerConfig { borrowed: true, lossless: true, ..Default::default() };
+        let tokens = Lexer::with_config(source, config).collect_spanned().unwrap();
+        let texts = tokens.iter()
+            .filter_map(|(token, span)| match token {
+                Token::Text(kind) => Some((*kind, token.text(source, span))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(texts, vec![
+            (TextKind::Symbol, "f"),
+            (TextKind::String, "\"a\\tb\""),
+            (TextKind::Keyword, ":k/v"),
+            (TextKind::Symbol, "x.y"),
+            (TextKind::Symbol, "|v w|"),
+            (TextKind::Tag, "#tag"),
+        ]);
+        assert!(tokens.iter().any(|(token, _)| *token == Token::Nil));
+        assert!(tokens.iter().any(|(token, _)| *token == Token::Comment { level: 1, text: String::new() }));
+
+        for source in ["\"ab\nc\\\"d", "\"a\\", "|a \\|b", "a..b", "a/b.c", ":a/", "#1 x"] {
+            let config = LexerConfig { borrowed: true, ..Default::default() };
+
+            assert_eq!(Lexer::with_config(source, config).collect_spanned().unwrap_err(), lex(source).unwrap_err(), "lexing {}", source);
         }
     }
 
+    #[test]
+    fn comment_prefixes() {
+        let config = LexerConfig { comment_prefixes: vec!["#".to_string(), "//".to_string()], ..Default::default() };
+        let tokens = Lexer::with_config("(a # comment\n b) // another\n## more\nc", config).collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(tokens, lex("(a\n b) c").unwrap());
+
+        let config = LexerConfig {
+            lossless: true,
+            comment_prefixes: vec!["//".to_string(), "".to_string(), "(".to_string()],
+            ..Default::default()
+        };
+        let tokens = Lexer::with_config("////a\n(b)", config).collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(tokens[0], Token::Comment { level: 2, text: "a".to_string() });
+        assert_eq!(tokens[2], Token::Open('('));
+    }
+
+    #[test]
+    fn error_tokens() {
+        let config = LexerConfig { error_tokens: true, ..Default::default() };
+        let tokens = Lexer::with_config("(a \0 b) ~", config).collect_spanned().unwrap();
+        let error = |message: &str, column| Token::Error(Box::new(super::LexError { message: message.to_string(), position: pos(1, column), file: None }));
+
+        assert_eq!(tokens.iter().map(|(token, _)| token.clone()).collect::<Vec<_>>(), vec![
+            Token::Open('('),
+            lex("a").unwrap()[0].clone(),
+            error("Unexpected null character", 4),
+            lex("b").unwrap()[0].clone(),
+            Token::Close(')'),
+            error("Unexpected character: ~", 9),
+        ]);
+        assert_eq!(tokens[2].1.range, 3..4);
+        assert!(lex("(a \0 b)").is_err());
+
+        let tokens = Lexer::with_config(":ab$c d ~x)", LexerConfig { error_tokens: true, ..Default::default() }).collect_spanned().unwrap();
+        assert_eq!(tokens.iter().map(|(token, span)| (token.clone(), span.range.clone())).collect::<Vec<_>>(), vec![
+            (error("Unexpected character: $ while parsing the keyword `:ab`", 4), 0..5),
+            (lex("d").unwrap()[0].clone(), 6..7),
+            (error("Unexpected character: ~", 9), 8..10),
+            (Token::Close(')'), 10..11),
+        ]);
+    }
+
+    #[test]
+    fn comment_levels() {
+        let source = "; inline\n;; section\n;;; top-level doc\n;;;";
+        let comments = lex_lossless(source).unwrap()
+            .into_iter()
+            .filter(|(token, _)| !matches!(token, Token::Whitespace(_)))
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>();
+
+        assert_eq!(comments, vec![
+            Token::Comment { level: 1, text: " inline".to_string() },
+            Token::Comment { level: 2, text: " section".to_string() },
+            Token::Comment { level: 3, text: " top-level doc".to_string() },
+            Token::Comment { level: 3, text: "".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn plus_signed_numbers() {
+        let symbol = |head: &str| Token::Symbol(Box::new(Symbol { head: head.to_string(), tail: vec![], slash: false }));
+        let config = LexerConfig { plus_signed_numbers: false, ..Default::default() };
+        let lex_unsigned = |source| Lexer::with_config(source, config.clone()).collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(lex("+5 -5 +").unwrap(), vec![Token::Integer(5), Token::Integer(-5), symbol("+")]);
+        assert_eq!(lex_unsigned("+5 -5 +"), vec![symbol("+5"), Token::Integer(-5), symbol("+")]);
+    }
+
+    #[test]
+    fn peek_token() {
+        let mut lexer = Lexer::new("(foo 1)");
+
+        assert_eq!(lexer.peek_token(), Some(&Ok(Token::Open('('))));
+        assert_eq!(lexer.peek_token(), Some(&Ok(Token::Open('('))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Open('('))));
+        assert!(matches!(lexer.next(), Some(Ok(Token::Symbol(_)))));
+        assert_eq!(lexer.peek_token(), Some(&Ok(Token::Integer(1))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Integer(1))));
+        assert_eq!(lexer.next(), Some(Ok(Token::Close(')'))));
+        assert_eq!(lexer.peek_token(), None);
+        assert_eq!(lexer.next(), None);
+    }
+
     #[test]
     fn sandbox() {
-        let source = "(fóo bar baz)";
-        let mut lexer = super::Lexer::new(source);
+        let source = "(fóo bar baz)\n(qux)";
+        let mut lexer = Lexer::new(source);
 
         assert_eq!(lexer.current, '(');
         assert_eq!(lexer.currentn(3), "(fó");
         assert_eq!(lexer.peek(), Some('f'));
-        assert_eq!(lexer.position(), (1, 1));
+        assert_eq!(lexer.current_position(), pos(1, 1));
         lexer.advance();
         assert_eq!(lexer.current, 'f');
-        assert_eq!(lexer.position(), (1, 2));
+        assert_eq!(lexer.current_position(), pos(1, 2));
         lexer.advancen(3);
         assert_eq!(lexer.current, ' ');
+        assert_eq!(lexer.current_position(), pos(1, 5));
         lexer.advance();
         assert_eq!(lexer.currentn(3), "bar");
         assert_eq!(lexer.current, 'b');
         assert_eq!(lexer.peek(), Some('a'));
+        lexer.advancen(8);
+        assert_eq!(lexer.current, '\n');
+        assert_eq!(lexer.current_position(), pos(1, 14));
+        lexer.advance();
+        assert_eq!(lexer.current, '(');
+        assert_eq!(lexer.current_position(), pos(2, 1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn keyword_and_symbol_edge_cases() {
+        let cases = vec![
+            (":", "Empty keyword", pos(1, 1)),
+            ("(:)", "Empty keyword", pos(1, 2)),
+            ("::", "Unexpected character: : while parsing the keyword `:`", pos(1, 2)),
+            (":a:", "Unexpected character: : while parsing the keyword `:a`", pos(1, 3)),
+            (".", "A symbol can't start with a `.`", pos(1, 1)),
+            (".a", "A symbol can't start with a `.`", pos(1, 1)),
+            ("a.", "A symbol can't end with a `.`", pos(1, 2)),
+            ("(a.b. c)", "A symbol can't end with a `.`", pos(1, 5)),
+            ("a..b", "A symbol can't have an empty part between `.`", pos(1, 3)),
+            ("(:a/b/c)", "The keyword `:a/b/c` can have only one `/`", pos(1, 2)),
+            (":ns/", "The keyword `:ns/` has an empty namespace or name", pos(1, 1)),
+            (":/name", "The keyword `:/name` has an empty namespace or name", pos(1, 1)),
+        ];
+
+        for (source, message, position) in cases {
+            let err = lex(source).unwrap_err();
+            assert_eq!(err.message, message, "lexing `{}`", source);
+            assert_eq!(err.position, position, "lexing `{}`", source);
+        }
+
+        assert_eq!(lex(":foo").unwrap(), vec![Token::Keyword(Keyword::new("foo").unwrap())]);
+        assert_eq!(lex("a.b").unwrap(), vec![Token::Symbol(Box::new(Symbol { head: "a".to_string(), tail: vec!["b".to_string()], slash: false }))]);
+    }
+
+    #[test]
+    fn namespaced_symbol() {
+        let symbol = |head: &str, tail: &[&str], slash| Token::Symbol(Box::new(Symbol {
+            head: head.to_string(),
+            tail: tail.iter().map(|part| part.to_string()).collect(),
+            slash,
+        }));
+
+        assert_eq!(lex("ns/name").unwrap(), vec![symbol("ns", &["name"], true)]);
+        assert_eq!(lex("a.b/c").unwrap(), vec![symbol("a", &["b", "c"], true)]);
+        assert_eq!(lex("(/ nil/x)").unwrap()[1..3], [symbol("/", &[], false), symbol("nil", &["x"], true)]);
+
+        let errors = vec![
+            ("a/b/c", "The symbol `a/b/c` can have only one `/`", pos(1, 1)),
+            ("(x a.b/c/d)", "The symbol `a.b/c/d` can have only one `/`", pos(1, 4)),
+            ("ns/", "The symbol `ns/` has an empty name after its `/`", pos(1, 1)),
+            ("a/b.c", "The symbol `a/b.c` can't have a `.` after its `/`", pos(1, 1)),
+        ];
+
+        for (source, message, position) in errors {
+            let err = lex(source).unwrap_err();
+            assert_eq!(err.message, message, "lexing `{}`", source);
+            assert_eq!(err.position, position, "lexing `{}`", source);
+        }
+    }
+
+    #[test]
+    fn source_and_remaining() {
+        let source = "(foo \"bar\") baz";
+        let mut lexer = Lexer::new(source);
+
+        assert_eq!(lexer.remaining(), source);
+
+        lexer.next();
+        lexer.next();
+        assert_eq!(lexer.remaining(), " \"bar\") baz");
+
+        lexer.next();
+        assert_eq!(lexer.remaining(), ") baz");
+        assert_eq!(lexer.source(), source);
+
+        lexer.by_ref().for_each(drop);
+        assert_eq!(lexer.remaining(), "");
+    }
+
+    #[test]
+    fn line_ending() {
+        assert_eq!(detect_line_ending("(a)\n(b)\n"), LineEnding::Lf);
+        assert_eq!(detect_line_ending("(a)"), LineEnding::Lf);
+        assert_eq!(detect_line_ending("(a)\r\n(b)\r\n"), LineEnding::CrLf);
+        assert_eq!(detect_line_ending("(a)\r\n(b)\n"), LineEnding::Mixed);
+    }
+
+    #[test]
+    fn unicode_whitespace() {
+        let nbsp = '\u{A0}';
+        let tokens = lex(&format!("(foo{nbsp}:bar{nbsp}\\a{nbsp}1{nbsp}#x1\u{2003}##Inf{nbsp})")).unwrap();
+
+        assert_eq!(tokens, lex("(foo :bar \\a 1 #x1 ##Inf)").unwrap());
+    }
+
+    #[test]
+    fn empty_source() {
+        assert_eq!(lex(""), Ok(vec![]));
+        assert_eq!(lex(" \n\t"), Ok(vec![]));
+        assert_eq!(lex("; only a comment"), Ok(vec![]));
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let tokens = lex_spanned("\u{FEFF}(a b)").unwrap();
+
+        assert_eq!(tokens.iter().map(|(token, _)| token.clone()).collect::<Vec<_>>(), lex("(a b)").unwrap());
+        assert_eq!(tokens[0].1.start, pos(1, 1));
+        assert_eq!(tokens[0].1.range, 3..4);
+        assert_eq!(lex("\u{FEFF}"), Ok(vec![]));
+
+        let err = lex("(a \u{FEFF}b)").unwrap_err();
+        assert_eq!(err.message, "Unexpected character: \u{FEFF}");
+        assert_eq!(err.position, pos(1, 4));
+    }
+
+    #[test]
+    fn shebang() {
+        let source = "#!/usr/bin/env rlispy\n(print 1)";
+
+        assert_eq!(lex(source).unwrap(), lex("(print 1)").unwrap());
+        assert_eq!(lex("\u{FEFF}#!rlispy\n1").unwrap(), vec![Token::Integer(1)]);
+        assert_eq!(lex_lossless(source).unwrap()[0].0, Token::Comment { level: 0, text: "/usr/bin/env rlispy".to_string() });
+
+        let tokens = lex("1\n#!foo").unwrap();
+        assert_eq!(tokens[1], Token::Symbol(Box::new(Symbol { head: "#!foo".to_string(), tail: vec![], slash: false })));
+        let tokens = lex(" #!foo").unwrap();
+        assert_eq!(tokens[0], Token::Symbol(Box::new(Symbol { head: "#!foo".to_string(), tail: vec![], slash: false })));
+    }
+
+    #[test]
+    fn embedded_null() {
+        let err = lex("(a \0 b)").unwrap_err();
+
+        assert_eq!(err.message, "Unexpected null character");
+        assert_eq!(err.position, pos(1, 4));
+        assert_eq!(lex("a\0").unwrap_err().position, pos(1, 2));
+        assert_eq!(lex("\"a\0\"").unwrap(), vec![Token::String("a\0".to_string())]);
+    }
+
+    #[test]
+    fn keyword_chars() {
+        let err = lex(":FooBar").unwrap_err();
+        assert_eq!(err.message, "Unexpected character: F while parsing the keyword `:`");
+
+        let config = LexerConfig { keyword_chars: SYMBOL_CHARS.to_string(), ..Default::default() };
+        let tokens = Lexer::with_config(":FooBar :a?", config).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(tokens, vec![Token::Keyword(Keyword::lexed("FooBar".to_string())), Token::Keyword(Keyword::lexed("a?".to_string()))]);
+    }
+
+    #[test]
+    fn render() {
+        let err = lex("(foo\n\t:)").unwrap_err();
+
+        assert_eq!(err.render("(foo\n\t:)"), "error: Empty keyword\n --> 2:2\n  |\n2 | \t:)\n  | \t^");
+    }
+
+    #[test]
+    #[cfg(feature = "colored")]
+    fn render_colored() {
+        let err = lex("(foo\n\t:)").unwrap_err();
+
+        assert_eq!(
+            err.render_colored("(foo\n\t:)"),
+            "\x1b[1;31merror\x1b[0m\x1b[1m: Empty keyword\x1b[0m\n --> 2:2\n  |\n2 | \t:)\n  | \t\x1b[31m^\x1b[0m",
+        );
+        assert_eq!(err.render("(foo\n\t:)"), "error: Empty keyword\n --> 2:2\n  |\n2 | \t:)\n  | \t^");
+        assert!(!err.to_string().contains('\x1b'));
+    }
+
+    #[test]
+    fn error_position() {
+        let err = lex("(foo\n  :)").unwrap_err();
+
+        assert_eq!(err.position, pos(2, 3));
+        assert_eq!(err.to_string(), "Empty keyword at 2:3");
+    }
+}
@@ -0,0 +1,104 @@
+//! Conversions between the `kebab-case`, `snake_case` and `camelCase` spellings of names.
+
+/// The spelling to convert a name to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Case {
+    Kebab,
+    Snake,
+    Camel,
+}
+
+/// Converts a single name to `case`. Words are split on `-`, `_` and before an uppercase letter
+/// following a lowercase letter or a digit. Digits stay in their word, so `vec-2d` is `vec_2d` or `vec2d`.
+/// Leading and trailing `-` and `_` are kept, as the separator of `case`, or as `_` for camel case.
+pub(crate) fn convert(name: &str, case: Case) -> String {
+    let is_separator = |c: char| c == '-' || c == '_';
+    let core = name.trim_matches(is_separator);
+
+    if core.is_empty() {
+        return name.to_string();
+    }
+
+    let edge = match case {
+        Case::Kebab => '-',
+        Case::Snake | Case::Camel => '_',
+    };
+    let leading = name.len() - name.trim_start_matches(is_separator).len();
+    let trailing = name.len() - name.trim_end_matches(is_separator).len();
+
+    let mut converted = edge.to_string().repeat(leading);
+
+    for (i, word) in words(core).iter().enumerate() {
+        match case {
+            Case::Kebab | Case::Snake if i > 0 => converted.push(edge),
+            Case::Camel if i > 0 => {
+                let mut chars = word.chars();
+                converted.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+                converted.extend(chars);
+                continue;
+            },
+            _ => {},
+        }
+
+        converted.push_str(word);
+    }
+
+    converted.push_str(&edge.to_string().repeat(trailing));
+
+    converted
+}
+
+/// Splits a name without leading or trailing separators into its lowercase words.
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut previous: Option<char> = None;
+
+    for c in name.chars() {
+        if c == '-' || c == '_' {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+        } else {
+            if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+
+            word.extend(c.to_lowercase());
+        }
+
+        previous = Some(c);
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, Case};
+
+    #[test]
+    fn convert_cases() {
+        let cases = vec![
+            ("my-cool-fn", "my-cool-fn", "my_cool_fn", "myCoolFn"),
+            ("myCoolFn", "my-cool-fn", "my_cool_fn", "myCoolFn"),
+            ("my_cool__fn", "my-cool-fn", "my_cool_fn", "myCoolFn"),
+            ("-private-fn", "-private-fn", "_private_fn", "_privateFn"),
+            ("fn-", "fn-", "fn_", "fn_"),
+            ("vec-2d", "vec-2d", "vec_2d", "vec2d"),
+            ("utf8String", "utf8-string", "utf8_string", "utf8String"),
+            ("+", "+", "+", "+"),
+            ("--", "--", "--", "--"),
+        ];
+
+        for (name, kebab, snake, camel) in cases {
+            assert_eq!(convert(name, Case::Kebab), kebab, "kebab case of `{}`", name);
+            assert_eq!(convert(name, Case::Snake), snake, "snake case of `{}`", name);
+            assert_eq!(convert(name, Case::Camel), camel, "camel case of `{}`", name);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::{case::{convert, Case}, KEYWORD_CHARS};
+
+/// The name of a keyword, without its leading `:`.
+///
+/// A keyword may be namespaced as `ns/name`, with a single `/` between two non empty parts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Keyword(String);
+
+impl Keyword {
+    /// Builds a keyword, checking that it only has characters from [`KEYWORD_CHARS`]
+    /// besides the `/` of a namespace.
+    pub fn new(name: &str) -> Result<Keyword, String> {
+        Keyword::check_parts(name)?;
+
+        if let Some(c) = name.chars().find(|&c| c != '/' && !KEYWORD_CHARS.contains(c)) {
+            return Err(format!("Unexpected character: {} in the keyword `:{}`", c, name));
+        }
+
+        Ok(Keyword(name.to_string()))
+    }
+
+    /// Checks that a keyword isn't empty and has at most one `/`, between a non empty namespace and name.
+    /// Shared with the lexer, so that both accept the same namespaced keywords.
+    pub(crate) fn check_parts(name: &str) -> Result<(), String> {
+        match name.split_once('/') {
+            None if name.is_empty() => Err("Empty keyword".to_string()),
+            None => Ok(()),
+            Some((_, rest)) if rest.contains('/') => Err(format!("The keyword `:{}` can have only one `/`", name)),
+            Some((namespace, rest)) if !namespace.is_empty() && !rest.is_empty() => Ok(()),
+            Some(_) => Err(format!("The keyword `:{}` has an empty namespace or name", name)),
+        }
+    }
+
+    /// Builds a keyword already checked by the lexer, which may allow other characters.
+    pub(crate) fn lexed(name: String) -> Keyword {
+        Keyword(name)
+    }
+
+    /// Returns the keyword without its leading `:`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the namespace of a keyword like `:ns/name`, or `None` if it has no namespace.
+    pub fn namespace(&self) -> Option<&str> {
+        self.0.split_once('/').map(|(namespace, _)| namespace)
+    }
+
+    /// Returns the name of the keyword, which is the part after its namespace.
+    pub fn name(&self) -> &str {
+        self.0.split_once('/').map_or(&self.0, |(_, name)| name)
+    }
+
+    /// Converts the namespace and name to `kebab-case`, so `:myKey` becomes `:my-key`.
+    pub fn to_kebab_case(&self) -> Keyword {
+        self.convert(Case::Kebab)
+    }
+
+    /// Converts the namespace and name to `snake_case`, so `:my-key` becomes `:my_key`.
+    /// The result may have characters outside [`KEYWORD_CHARS`].
+    pub fn to_snake_case(&self) -> Keyword {
+        self.convert(Case::Snake)
+    }
+
+    /// Converts the namespace and name to `camelCase`, so `:my-key` becomes `:myKey`.
+    /// The result may have characters outside [`KEYWORD_CHARS`].
+    pub fn to_camel_case(&self) -> Keyword {
+        self.convert(Case::Camel)
+    }
+
+    fn convert(&self, case: Case) -> Keyword {
+        match self.0.split_once('/') {
+            Some((namespace, name)) => Keyword(format!("{}/{}", convert(namespace, case), convert(name, case))),
+            None => Keyword(convert(&self.0, case)),
+        }
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.pad(&format!(":{}", self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{lex, token::Token};
+
+    use super::Keyword;
+
+    #[test]
+    fn new() {
+        let keyword = Keyword::new("foo-bar").unwrap();
+        assert_eq!(keyword.as_str(), "foo-bar");
+        assert_eq!(keyword.namespace(), None);
+        assert_eq!(keyword.name(), "foo-bar");
+        assert_eq!(keyword.to_string(), ":foo-bar");
+
+        let keyword = Keyword::new("ns/foo").unwrap();
+        assert_eq!(keyword.namespace(), Some("ns"));
+        assert_eq!(keyword.name(), "foo");
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Keyword::new("foo bar"), Err("Unexpected character:   in the keyword `:foo bar`".to_string()));
+        assert_eq!(Keyword::new("Foo"), Err("Unexpected character: F in the keyword `:Foo`".to_string()));
+        assert_eq!(Keyword::new(""), Err("Empty keyword".to_string()));
+        assert_eq!(Keyword::new("ns/"), Err("The keyword `:ns/` has an empty namespace or name".to_string()));
+        assert_eq!(Keyword::new("a/b/c"), Err("The keyword `:a/b/c` can have only one `/`".to_string()));
+    }
+
+    #[test]
+    fn round_trip() {
+        for name in ["foo", "ns/foo", "my-ns/my-key"] {
+            let keyword = Keyword::new(name).unwrap();
+
+            assert_eq!(lex(&keyword.to_string()), Ok(vec![Token::Keyword(keyword)]), "lexing `:{}`", name);
+        }
+
+        for name in ["", "ns/", "/foo", "a/b/c", "Foo"] {
+            assert!(Keyword::new(name).is_err());
+            assert!(lex(&format!(":{}", name)).is_err(), "lexing `:{}`", name);
+        }
+    }
+
+    #[test]
+    fn case_conversion() {
+        let keyword = Keyword::new("my-ns/my-cool-key").unwrap();
+
+        assert_eq!(keyword.to_snake_case().as_str(), "my_ns/my_cool_key");
+        assert_eq!(keyword.to_camel_case().as_str(), "myNs/myCoolKey");
+        assert_eq!(keyword.to_camel_case().to_kebab_case(), keyword);
+    }
+}
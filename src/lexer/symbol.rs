@@ -1,5 +1,282 @@
-#[derive(Debug, Clone, PartialEq)]
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display, Formatter}, iter};
+
+use super::{case::{convert, Case}, SYMBOL_CHARS};
+
+/// The special forms in [`ReservedWords::default`].
+pub const RESERVED_WORDS: [&str; 10] = ["def", "defn", "defmacro", "fn", "let", "if", "do", "quote", "loop", "recur"];
+
+/// A set of names, like the special forms of a language, that symbols shouldn't take.
+/// Defaults to [`RESERVED_WORDS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedWords {
+    words: HashSet<String>,
+}
+
+/// A symbol made of parts separated by `.`, like `math.sqrt`, where the last part may instead
+/// be separated by a `/`, like `clojure.core/map`.
+///
+/// `head` is the first part and `tail` holds the rest, so `a.b.c` has the head `a` and the tail `[b, c]`.
+/// Every part but the last forms the namespace, and the last part is the name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Symbol {
     pub head: String,
     pub tail: Vec<String>,
-}
\ No newline at end of file
+    /// Whether the name is separated from the namespace by `/` rather than `.`, so `a.b/c` and `a.b.c`
+    /// have the same parts but are different symbols.
+    pub slash: bool,
+}
+
+impl Symbol {
+    /// Returns the namespace of the symbol, which is every part but the last joined by `.`,
+    /// or `None` for an unqualified symbol.
+    pub fn namespace(&self) -> Option<String> {
+        let (_, parts) = self.tail.split_last()?;
+        let mut namespace = self.head.clone();
+
+        for part in parts {
+            namespace.push('.');
+            namespace.push_str(part);
+        }
+
+        Some(namespace)
+    }
+
+    /// Returns the name of the symbol, which is its last part.
+    pub fn name(&self) -> &str {
+        self.tail.last().unwrap_or(&self.head)
+    }
+
+    /// Rewrites the namespace of the symbol using `ns_map`, which maps namespaces to the ones they stand for.
+    /// Unqualified symbols and namespaces missing from the map are returned unchanged.
+    pub fn resolve(&self, ns_map: &HashMap<String, String>) -> Symbol {
+        let Some(resolved) = self.namespace().and_then(|namespace| ns_map.get(&namespace)) else {
+            return self.clone();
+        };
+
+        let mut parts = resolved.split('.').map(str::to_string);
+        let head = parts.next().unwrap_or_default();
+        let tail = parts.chain([self.name().to_string()]).collect();
+
+        Symbol { head, tail, slash: self.slash }
+    }
+
+    /// Converts every part of the symbol to `kebab-case`, so `myFn` becomes `my-fn`.
+    pub fn to_kebab_case(&self) -> Symbol {
+        self.convert(Case::Kebab)
+    }
+
+    /// Converts every part of the symbol to `snake_case`, so `my-fn` becomes `my_fn`.
+    pub fn to_snake_case(&self) -> Symbol {
+        self.convert(Case::Snake)
+    }
+
+    /// Converts every part of the symbol to `camelCase`, so `my-fn` becomes `myFn`.
+    pub fn to_camel_case(&self) -> Symbol {
+        self.convert(Case::Camel)
+    }
+
+    /// Returns whether the symbol is unqualified and its name is one of the `reserved` words,
+    /// so `if` is reserved by default but `core.if` isn't.
+    pub fn is_reserved(&self, reserved: &ReservedWords) -> bool {
+        self.tail.is_empty() && reserved.contains(&self.head)
+    }
+
+    fn convert(&self, case: Case) -> Symbol {
+        Symbol {
+            head: convert(&self.head, case),
+            tail: self.tail.iter().map(|part| convert(part, case)).collect(),
+            slash: self.slash,
+        }
+    }
+
+    /// Returns whether `text`, the symbol written plainly, lexes back as the symbol with the default configuration.
+    /// The characters are checked directly, so that displaying a form doesn't lex every symbol in it again.
+    pub(crate) fn is_plain(&self, text: &str) -> bool {
+        let mut parts = iter::once(&self.head).chain(&self.tail);
+
+        if !parts.all(|part| !part.is_empty() && part.chars().all(|c| SYMBOL_CHARS.contains(c))) {
+            return false;
+        }
+
+        // Only the `/` of a slash symbol splits it, unless the symbol is a single part starting with `/`, like `/`
+        let slashes = text.matches('/').count();
+        let slash_ok = match (self.slash, self.tail.is_empty()) {
+            (true, false) => slashes == 1,
+            (true, true) => false,
+            (false, true) => slashes == 0 || self.head.starts_with('/'),
+            (false, false) => slashes == 0,
+        };
+
+        // The text must start a symbol, rather than a number, a reader macro, a verbatim symbol or a literal
+        let mut chars = text.chars().peekable();
+        chars.next_if(|&c| c == '-' || c == '+');
+        chars.next_if_eq(&'.');
+        let number = chars.next().is_some_and(char::is_numeric);
+        let literal = self.tail.is_empty() && matches!(self.head.as_str(), "nil" | "true" | "false");
+
+        slash_ok && !number && !literal && !text.starts_with(['#', '|'])
+    }
+}
+
+impl ReservedWords {
+    /// Builds a set holding only the given words.
+    pub fn new<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self { words: words.into_iter().map(Into::into).collect() }
+    }
+
+    /// Adds a word, returning whether it wasn't reserved yet.
+    pub fn insert(&mut self, word: impl Into<String>) -> bool {
+        self.words.insert(word.into())
+    }
+
+    /// Returns whether `word` is reserved.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+}
+
+impl Default for ReservedWords {
+    fn default() -> Self {
+        Self::new(RESERVED_WORDS)
+    }
+}
+
+impl Display for Symbol {
+    /// Writes the parts joined by `.`, with a `/` before the name of a `slash` symbol. A symbol that wouldn't lex
+    /// back as itself, like `123`, `true` or `#!foo`, is written in the verbatim `|...|` syntax instead. That syntax
+    /// has no parts, so a symbol with several parts reads back from it as a single part with the same text.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut s = self.head.clone();
+
+        for (i, part) in self.tail.iter().enumerate() {
+            s.push(if self.slash && i + 1 == self.tail.len() { '/' } else { '.' });
+            s.push_str(part);
+        }
+
+        if !self.is_plain(&s) {
+            s = format!("|{}|", s.replace('\\', "\\\\").replace('|', "\\|"));
+        }
+
+        f.pad(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::lexer::{lex, token::Token};
+
+    use super::{ReservedWords, Symbol};
+
+    fn symbol(s: &str) -> Symbol {
+        let mut parts = s.split('.').map(str::to_string);
+
+        Symbol { head: parts.next().unwrap(), tail: parts.collect(), slash: false }
+    }
+
+    #[test]
+    fn display() {
+        let symbol = Symbol { head: "math".to_string(), tail: vec!["sqrt".to_string()], slash: false };
+
+        assert_eq!(symbol.to_string(), "math.sqrt");
+        assert_eq!(format!("{:<12}|", symbol), "math.sqrt   |");
+    }
+
+    #[test]
+    fn display_verbatim() {
+        let verbatim = |head: &str| Symbol { head: head.to_string(), tail: vec![], slash: false };
+
+        assert_eq!(symbol("foo").to_string(), "foo");
+        assert_eq!(symbol("a.b").to_string(), "a.b");
+        assert_eq!(verbatim("123").to_string(), "|123|");
+        assert_eq!(verbatim("-1").to_string(), "|-1|");
+        assert_eq!(verbatim("nil").to_string(), "|nil|");
+        assert_eq!(verbatim("a b").to_string(), "|a b|");
+        assert_eq!(verbatim("a.b").to_string(), "|a.b|");
+        assert_eq!(verbatim("a|b\\").to_string(), "|a\\|b\\\\|");
+        assert_eq!(verbatim("").to_string(), "||");
+
+        for head in ["123", "a b", "a.b", "a|b\\", ""] {
+            let symbol = verbatim(head);
+            assert_eq!(lex(&symbol.to_string()).unwrap(), vec![Token::Symbol(Box::new(symbol))]);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let parts = ["a", "-", "1", "nil", "#!x", "/", "a b", "|x", "+4i", "x/y", "\u{a0}#!nil+ns", ""];
+
+        for head in parts {
+            let single = Symbol { head: head.to_string(), tail: vec![], slash: false };
+            assert_eq!(lex(&single.to_string()).unwrap(), vec![Token::Symbol(Box::new(single.clone()))], "{:?}", single);
+
+            for (name, slash) in parts.iter().flat_map(|name| [(name, false), (name, true)]) {
+                let symbol = Symbol { head: head.to_string(), tail: vec![name.to_string()], slash };
+                let text = symbol.to_string();
+                let expected = match text.strip_prefix('|') {
+                    // Written verbatim, as a single part with the same text
+                    Some(_) => Symbol { head: format!("{}{}{}", head, if slash { '/' } else { '.' }, name), ..single.clone() },
+                    None => symbol.clone(),
+                };
+
+                assert_eq!(lex(&text).unwrap(), vec![Token::Symbol(Box::new(expected))], "{:?}", symbol);
+            }
+        }
+
+        let symbol = Symbol { head: "\u{a0}#!nil+ns".to_string(), tail: vec!["x".to_string()], slash: true };
+        assert_eq!(symbol.to_string(), "|\u{a0}#!nil+ns/x|");
+    }
+
+    #[test]
+    fn namespace_and_name() {
+        assert_eq!(symbol("sqrt").namespace(), None);
+        assert_eq!(symbol("sqrt").name(), "sqrt");
+        assert_eq!(symbol("math.sqrt").namespace(), Some("math".to_string()));
+        assert_eq!(symbol("math.sqrt").name(), "sqrt");
+        assert_eq!(symbol("std.math.sqrt").namespace(), Some("std.math".to_string()));
+        assert_eq!(symbol("std.math.sqrt").name(), "sqrt");
+
+        let slashed = Symbol { slash: true, ..symbol("clojure.core.map") };
+
+        assert_eq!(slashed.namespace(), Some("clojure.core".to_string()));
+        assert_eq!(slashed.name(), "map");
+        assert_eq!(slashed.to_string(), "clojure.core/map");
+        assert_ne!(slashed, symbol("clojure.core.map"));
+    }
+
+    #[test]
+    fn resolve() {
+        let ns_map = HashMap::from([
+            ("m".to_string(), "std.math".to_string()),
+            ("std.io".to_string(), "io".to_string()),
+        ]);
+
+        assert_eq!(symbol("m.sqrt").resolve(&ns_map), symbol("std.math.sqrt"));
+        assert_eq!(symbol("std.io.print").resolve(&ns_map), symbol("io.print"));
+        assert_eq!(symbol("x.sqrt").resolve(&ns_map), symbol("x.sqrt"));
+        assert_eq!(symbol("m").resolve(&ns_map), symbol("m"));
+    }
+
+    #[test]
+    fn case_conversion() {
+        assert_eq!(symbol("my-cool-fn").to_snake_case(), symbol("my_cool_fn"));
+        assert_eq!(symbol("my-cool-fn").to_camel_case(), symbol("myCoolFn"));
+        assert_eq!(symbol("my-lib.my-cool-fn").to_camel_case(), symbol("myLib.myCoolFn"));
+        assert_eq!(symbol("myLib.myCoolFn").to_kebab_case(), symbol("my-lib.my-cool-fn"));
+    }
+
+    #[test]
+    fn reserved() {
+        let mut reserved = ReservedWords::new(["fn", "match"]);
+        reserved.insert("struct");
+
+        assert!(symbol("match").is_reserved(&reserved));
+        assert!(symbol("struct").is_reserved(&reserved));
+        assert!(!symbol("let").is_reserved(&reserved));
+        assert!(!symbol("core.match").is_reserved(&reserved));
+
+        assert!(symbol("let").is_reserved(&ReservedWords::default()));
+        assert!(!symbol("my-let").is_reserved(&ReservedWords::default()));
+    }
+}
@@ -1,13 +1,257 @@
-use super::symbol::Symbol;
+use std::fmt::{self, Display, Formatter};
+
+use super::{keyword::Keyword, symbol::Symbol, LexError, Span};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Integer(i64),
     Float(f64),
+    /// A complex number like `3+4i`.
+    #[cfg(feature = "complex")]
+    Complex { re: f64, im: f64 },
     String(String),
     Char(char),
-    Symbol(Symbol),
-    Keyword(String),
+    /// Boxed to keep the enum small, since a symbol is the largest payload.
+    Symbol(Box<Symbol>),
+    Keyword(Keyword),
+    Bool(bool),
+    Nil,
     Open(char),
+    /// The `#{` opening a set, which is closed by `}`.
+    OpenSet,
     Close(char),
-}
\ No newline at end of file
+    Quote,
+    Discard,
+    /// The `#?` starting a reader conditional, which is followed by a call of feature/form pairs.
+    ReaderConditional,
+    /// The tag of a tagged literal like `#inst "2024-01-01"`, which is followed by its value.
+    Tag(Box<Symbol>),
+    Whitespace(String),
+    /// A line comment. `level` is the number of leading `;` and `text` is the rest of the line.
+    /// A `#!` shebang line is a comment of level 0.
+    Comment { level: usize, text: String },
+    /// A string, keyword, symbol or tag lexed with [`LexerConfig::borrowed`](super::LexerConfig::borrowed) set,
+    /// whose text is left in the source at the span of the token.
+    Text(TextKind),
+    /// An error found while lexing, emitted in place of the offending text when
+    /// [`LexerConfig::error_tokens`](super::LexerConfig::error_tokens) is set. Boxed to keep tokens small.
+    Error(Box<LexError>),
+}
+
+/// The kind of a token whose text is left in the source, see [`Token::Text`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+    String,
+    Keyword,
+    Symbol,
+    Tag,
+}
+
+impl Token {
+    /// Compares two tokens like `==`, except that `Float(NaN)` equals `Float(NaN)`.
+    ///
+    /// The derived `PartialEq` follows IEEE 754, where `NaN` is unequal to everything,
+    /// which is surprising when comparing or deduplicating tokens. `-0.0` and `0.0`
+    /// are equal under both, so use `f64::to_bits` if the sign of zero matters.
+    pub fn semantic_eq(&self, other: &Token) -> bool {
+        match (self, other) {
+            (Token::Float(a), Token::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            _ => self == other,
+        }
+    }
+
+    /// Returns the exact text of the token in `source`, given its span, like `1.50` for `Float(1.5)`.
+    ///
+    /// Panics if the span is out of `source`, so it must come from lexing that same source.
+    pub fn text<'a>(&self, source: &'a str, span: &Span) -> &'a str {
+        &source[span.range.clone()]
+    }
+}
+
+/// Formats a complex number like `3.0+4.0i`, so that lexing the result gives back the same value.
+#[cfg(feature = "complex")]
+pub fn format_complex(re: f64, im: f64) -> String {
+    let sign = if im.is_sign_negative() { "" } else { "+" };
+
+    format!("{}{}{}i", format_float(re), sign, format_float(im))
+}
+
+/// Formats a float so that lexing the result gives back the same value.
+///
+/// Uses the shortest representation that round-trips and always includes a
+/// decimal point, so `3.0` is written as `3.0` and never as `3`.
+/// Special values are spelled `##Inf`, `##-Inf` and `##NaN`.
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "##NaN".to_string();
+    }
+
+    if f.is_infinite() {
+        return if f > 0.0 { "##Inf" } else { "##-Inf" }.to_string();
+    }
+
+    let mut s = f.to_string();
+
+    if !s.contains('.') {
+        s.push_str(".0");
+    }
+
+    s
+}
+
+/// Formats a string as a string literal, escaping quotes, backslashes and control characters.
+pub fn format_string(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+
+    literal.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            c => literal.push(c),
+        }
+    }
+
+    literal.push('"');
+
+    literal
+}
+
+/// Formats a char as a char literal, using names for whitespace like `\newline`.
+pub fn format_char(c: char) -> String {
+    match c {
+        '\n' => "\\newline".to_string(),
+        '\r' => "\\return".to_string(),
+        '\t' => "\\tab".to_string(),
+        ' ' => "\\space".to_string(),
+        c => format!("\\{}", c),
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let s = match self {
+            Token::Integer(i) => i.to_string(),
+            Token::Float(n) => format_float(*n),
+            #[cfg(feature = "complex")]
+            Token::Complex { re, im } => format_complex(*re, *im),
+            Token::String(s) => format_string(s),
+            Token::Char(c) => format_char(*c),
+            Token::Symbol(symbol) => symbol.to_string(),
+            Token::Keyword(k) => k.to_string(),
+            Token::Bool(b) => b.to_string(),
+            Token::Nil => "nil".to_string(),
+            Token::Open(c) | Token::Close(c) => c.to_string(),
+            Token::OpenSet => "#{".to_string(),
+            Token::Quote => "'".to_string(),
+            Token::Discard => "#_".to_string(),
+            Token::ReaderConditional => "#?".to_string(),
+            Token::Tag(tag) => format!("#{}", tag),
+            Token::Whitespace(s) => s.clone(),
+            Token::Comment { level: 0, text } => format!("#!{}", text),
+            Token::Comment { level, text } => format!("{}{}", ";".repeat(*level), text),
+            Token::Text(kind) => format!("<{}>", kind),
+            Token::Error(err) => err.to_string(),
+        };
+
+        f.pad(&s)
+    }
+}
+
+impl Display for TextKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let s = match self {
+            TextKind::String => "string",
+            TextKind::Keyword => "keyword",
+            TextKind::Symbol => "symbol",
+            TextKind::Tag => "tag",
+        };
+
+        f.pad(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::lex_spanned;
+
+    use super::{format_float, Keyword, Symbol, Token};
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn token_size() {
+        const _: () = assert!(std::mem::size_of::<Token>() == 32);
+    }
+
+    #[test]
+    fn display() {
+        let cases = vec![
+            (Token::Integer(-1), "-1"),
+            (Token::Float(2.0), "2.0"),
+            (Token::String("a \"b\"\n".to_string()), "\"a \\\"b\\\"\\n\""),
+            (Token::Char('a'), "\\a"),
+            (Token::Char(' '), "\\space"),
+            (Token::Symbol(Box::new(Symbol { head: "a".to_string(), tail: vec!["b".to_string()], slash: false })), "a.b"),
+            (Token::Keyword(Keyword::new("k").unwrap()), ":k"),
+            (Token::Bool(true), "true"),
+            (Token::Nil, "nil"),
+            (Token::Open('('), "("),
+            (Token::OpenSet, "#{"),
+            (Token::Quote, "'"),
+            (Token::Comment { level: 2, text: " doc".to_string() }, ";; doc"),
+        ];
+
+        for (token, expected) in cases {
+            assert_eq!(token.to_string(), expected);
+        }
+
+        assert_eq!(format!("{:>4}", Token::Integer(1)), "   1");
+    }
+
+    #[test]
+    fn semantic_eq() {
+        assert_ne!(Token::Float(f64::NAN), Token::Float(f64::NAN));
+        assert!(Token::Float(f64::NAN).semantic_eq(&Token::Float(f64::NAN)));
+        assert!(Token::Float(-0.0).semantic_eq(&Token::Float(0.0)));
+        assert!(Token::Float(1.5).semantic_eq(&Token::Float(1.5)));
+        assert!(!Token::Float(f64::NAN).semantic_eq(&Token::Float(1.0)));
+        assert!(!Token::Float(1.0).semantic_eq(&Token::Integer(1)));
+        assert!(Token::Integer(1).semantic_eq(&Token::Integer(1)));
+    }
+
+    #[test]
+    fn format_float_round_trips() {
+        let floats = vec![3.0, 0.1, -1.5, 1e20, 123456789.125, -0.0];
+
+        for f in floats {
+            let s = format_float(f);
+            assert!(s.contains('.'), "`{}` has no decimal point", s);
+            assert_eq!(s.parse::<f64>().unwrap().to_bits(), f.to_bits());
+        }
+
+        assert_eq!(format_float(3.0), "3.0");
+        assert_eq!(format_float(0.1), "0.1");
+        assert_eq!(format_float(1e20), "100000000000000000000.0");
+    }
+
+    #[test]
+    fn text() {
+        let source = "(f 1.50 \"a\\tb\")";
+        let tokens = lex_spanned(source).unwrap();
+        let texts: Vec<_> = tokens.iter().map(|(token, span)| token.text(source, span)).collect();
+
+        assert_eq!(tokens[2].0, Token::Float(1.5));
+        assert_eq!(texts, vec!["(", "f", "1.50", "\"a\\tb\"", ")"]);
+    }
+
+    #[test]
+    fn format_float_special_values() {
+        assert_eq!(format_float(f64::INFINITY), "##Inf");
+        assert_eq!(format_float(f64::NEG_INFINITY), "##-Inf");
+        assert_eq!(format_float(f64::NAN), "##NaN");
+    }
+}
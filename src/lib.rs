@@ -1,2 +1,17 @@
+/// Logs a debug trace through `log` when the `trace` feature is on, and expands to nothing otherwise.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace")]
+        log::debug!($($arg)*);
+    };
+}
+
+pub mod ast;
+#[cfg(feature = "serde")]
+pub mod json;
 pub mod lexer;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod shape;
+pub mod source;
+#[cfg(feature = "toml")]
+pub mod toml;
@@ -1,112 +1,2551 @@
-use std::{iter::Peekable, vec::IntoIter};
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt::{self, Display, Formatter}, hash::{Hash, Hasher}, iter::Peekable, mem, sync::Arc, vec::IntoIter};
 
-use crate::lexer::{symbol::Symbol, token::Token};
+use crate::ast::DepthFirst;
+#[cfg(feature = "complex")]
+use crate::lexer::token::format_complex;
+use crate::lexer::{keyword::Keyword, lex, lex_spanned, location, render_styled, symbol::Symbol, token::{format_char, format_float, format_string, TextKind, Token}, LexError, Lexer, LexerConfig, Position, Span, Style};
+
+pub mod borrowed;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Form {
     Call(Vec<Form>),
-    Symbol(Symbol),
+    /// Boxed to keep the enum small, since a symbol is the largest payload.
+    Symbol(Box<Symbol>),
     Float(f64),
     Integer(i64),
-    String(String),
+    /// A complex number like `3+4i`.
+    #[cfg(feature = "complex")]
+    Complex { re: f64, im: f64 },
+    /// Shared, so equal literals can use the same allocation when the parser interns strings.
+    String(Arc<str>),
     Char(char),
-    Keyword(String),
+    Keyword(Keyword),
+    Bool(bool),
+    Nil,
     List(Vec<Form>),
     Map(Vec<(Form, Form)>),
+    Set(Vec<Form>),
+    /// A form read after `'`, like any atom or collection in `'x` or `'[1 2]`.
+    ///
+    /// Reader macros (`'`, `#_` and `#?`) apply to the whole form after them, which is read along
+    /// with its own reader macros first, so they nest from right to left: `''x` is a quote of `'x`,
+    /// `'#_a b` is `'b` and `#_'a b` is `b`.
+    Quote(Box<Form>),
+    /// A tagged literal like `#inst "2024-01-01"`: a tag symbol right after `#`, and the form after it.
+    Tagged { tag: Box<Symbol>, value: Box<Form> },
 }
 
-pub fn parse(mut tokens: Peekable<IntoIter<Token>>) -> Result<(Form, Peekable<IntoIter<Token>>), String> {
-    loop {
-        match tokens.next() {
-            None => return Err(format!("Unexpected end of input")),
-            Some(token) => match token {
-                Token::Open('(') => {
-                    let form = parse_call(tokens)?;
-                    return Ok(form);
-                },
-                Token::Open('[') => {
-                    let form = parse_list(tokens)?;
-                    return Ok(form);
-                },
-                Token::Open('{') => {
-                    let form = parse_map(tokens)?;
-                    return Ok(form);
-                },
-                Token::Integer(i) => return Ok((Form::Integer(i), tokens)),
-                Token::Float(f) => return Ok((Form::Float(f), tokens)),
-                Token::String(s) => return Ok((Form::String(s), tokens)),
-                Token::Char(c) => return Ok((Form::Char(c), tokens)),
-                Token::Symbol(s) => return Ok((Form::Symbol(s), tokens)),
-                Token::Keyword(k) => return Ok((Form::Keyword(k), tokens)),
-                _ => return Err(format!("Unexpected token: {:?}", token)),
+/// The variant of a [`Form`], without its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormKind {
+    Call,
+    Symbol,
+    Float,
+    Integer,
+    #[cfg(feature = "complex")]
+    Complex,
+    String,
+    Char,
+    Keyword,
+    Bool,
+    Nil,
+    List,
+    Map,
+    Set,
+    Quote,
+    Tagged,
+}
+
+/// A map with constant time lookups that keeps the order of its entries.
+///
+/// Built from a `Map` form with [`Form::index_map`]. When a key appears more than once,
+/// lookups find its first value, like [`Form::map_get`].
+#[derive(Debug, Clone)]
+pub struct IndexedMap {
+    entries: Vec<(Form, Form)>,
+    index: HashMap<Form, usize>,
+}
+
+/// A step into a child of a form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    /// The child at an index of a `Call`, `List` or `Set`.
+    Index(usize),
+    /// The value for a key of a `Map`.
+    MapKey(Form),
+}
+
+/// A sequence of steps leading from a form to one of its descendants.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FormPath(pub Vec<PathSegment>);
+
+/// How closing brackets are matched against opening ones.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BracketMode {
+    /// A closing bracket must match its opening one: `(` with `)`, `[` with `]` and `{` with `}`.
+    #[default]
+    Strict,
+    /// Any closing bracket closes any opening one. The opening bracket decides the form.
+    Lenient,
+}
+
+/// Options that change how the parser reads forms.
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    /// Defaults to [`BracketMode::Strict`].
+    pub brackets: BracketMode,
+    /// Whether `()` is kept as an empty [`Form::Call`] instead of being rejected. Defaults to `false`.
+    pub allow_empty_call: bool,
+    /// Whether maps, calls and sets can be used as map keys. Defaults to `true`.
+    pub allow_compound_keys: bool,
+    /// Whether equal string literals share a single allocation. Defaults to `false`.
+    pub intern_strings: bool,
+    /// The active features of reader conditionals like `#?(:a x :b y)`, which read as the form
+    /// of the first active feature, or as nothing when none is active. Defaults to no features.
+    pub features: HashSet<Keyword>,
+    /// How deep forms can nest, counting every collection, quote and map key or value, before
+    /// parsing fails instead of overflowing the stack. Defaults to 256.
+    pub max_depth: usize,
+    /// How many forms can be parsed in total, counting every nested form, before parsing fails.
+    /// Defaults to no limit.
+    pub max_nodes: Option<usize>,
+    /// Reads a dotted symbol as a method call, so `obj.method` is `(. obj method)` and `a.b.c` is
+    /// `(. (. a b) c)`. Defaults to `false`, keeping the dotted symbol.
+    pub dot_as_method_call: bool,
+    /// Joins adjacent string literals, separated only by whitespace and comments, into a single string,
+    /// so `"foo" "bar"` is `"foobar"`, and `#_"foo" "bar"` discards both. Defaults to `false`, since it changes
+    /// how many forms there are.
+    pub concat_strings: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            brackets: BracketMode::default(),
+            allow_empty_call: false,
+            allow_compound_keys: true,
+            intern_strings: false,
+            features: HashSet::new(),
+            max_depth: 256,
+            max_nodes: None,
+            dot_as_method_call: false,
+            concat_strings: false,
+        }
+    }
+}
+
+/// An error found while parsing, with the position of the offending token when it's known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: Option<Position>,
+    /// The name of the file the error was found in, when it's known.
+    pub file: Option<String>,
+    /// A short suggestion on how to fix the error, for the common ones.
+    pub hint: Option<String>,
+    fixes: Vec<Fix>,
+}
+
+/// An edit to the source that would fix a [`ParseError`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fix {
+    /// Inserts the token before the given position, or at the end of the input when there's no position.
+    Insert { position: Option<Position>, token: Token },
+    /// Deletes the token at the given position.
+    Delete { position: Option<Position>, token: Token },
+}
+
+/// The result of [`Parser::parse_recovering`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveredForms {
+    /// Every form parsed without errors.
+    pub forms: Vec<Form>,
+    pub errors: Vec<ParseError>,
+    /// Whether parsing stopped early because the error limit was reached.
+    pub truncated: bool,
+}
+
+/// A top level form with the docs of the forms in it, returned by [`Parser::parse_documented`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Documented {
+    pub form: Form,
+    /// The doc of each documented form, by its path from `form`.
+    docs: Vec<(FormPath, String)>,
+}
+
+pub struct Parser<B: Build = FormBuilder> {
+    tokens: Peekable<IntoIter<Token>>,
+    /// Spans of the remaining tokens, empty when the parser was built without spans.
+    spans: VecDeque<Span>,
+    config: ParserConfig,
+    /// Builds the forms read.
+    build: B,
+    /// How many brackets consumed so far are still open.
+    depth: usize,
+    /// The form selected by a reader conditional, which is the next form to be parsed.
+    pending: Option<B::Form>,
+    /// How many forms are being parsed, one inside the other.
+    nesting: usize,
+    /// How many forms were parsed so far, counting nested ones.
+    nodes: usize,
+    /// Estimated number of forms inside each remaining opening bracket, in order, used to pre-size collections.
+    /// Empty when the parser was built from a token iterator.
+    capacities: VecDeque<usize>,
+    /// Estimated number of forms inside the last consumed opening bracket.
+    opened_capacity: usize,
+    /// The path of the form being parsed from its top level form, when recording docs.
+    doc_path: Option<Vec<PathSegment>>,
+    /// The comment block read since the last form, when recording docs.
+    doc: Option<String>,
+    /// The docs recorded in the current top level form.
+    docs: Vec<(FormPath, String)>,
+    /// The path of the form that just ended, until the end of its line, so a comment after it on that line is its doc.
+    trailing: Option<FormPath>,
+}
+
+/// How a [`Parser`] builds the forms it reads, so the same grammar can build other kinds of forms than [`Form`].
+pub trait Build {
+    type Form;
+
+    /// Builds the form of an atom or symbol token, given the span of the token when the parser has spans.
+    /// Errors on tokens that aren't forms by themselves.
+    fn atom(&mut self, token: Token, span: Option<&Span>, config: &ParserConfig) -> Result<Self::Form, String>;
+    /// Builds a tagged literal from the token of its tag and its value.
+    fn tagged(&mut self, tag: Token, span: Option<&Span>, value: Self::Form) -> Result<Self::Form, String>;
+    fn call(&mut self, forms: Vec<Self::Form>) -> Self::Form;
+    fn list(&mut self, forms: Vec<Self::Form>) -> Self::Form;
+    fn map(&mut self, entries: Vec<(Self::Form, Self::Form)>) -> Self::Form;
+    fn set(&mut self, forms: Vec<Self::Form>) -> Self::Form;
+    fn quote(&mut self, form: Self::Form) -> Self::Form;
+    /// Returns the variant of a form, for the checks and errors of the parser.
+    fn kind(form: &Self::Form) -> FormKind;
+    /// Returns the name of a keyword form without its `:`, `None` for other forms.
+    fn keyword(form: &Self::Form) -> Option<&str>;
+}
+
+/// Builds the [`Form`]s of a [`Parser`], interning strings when the configuration asks for it.
+#[derive(Debug, Default)]
+pub struct FormBuilder {
+    /// String literals seen so far, when interning them.
+    strings: HashSet<Arc<str>>,
+}
+
+/// Parses a single form from the tokens, returning it along with the remaining tokens.
+pub fn parse(tokens: Peekable<IntoIter<Token>>) -> Result<(Form, Peekable<IntoIter<Token>>), ParseError> {
+    let mut parser = Parser::from_parts(tokens, VecDeque::new(), ParserConfig::default(), FormBuilder::default());
+    let form = parser.parse()?;
+
+    Ok((form, parser.tokens))
+}
+
+/// Lexes and parses every form in the source.
+pub fn parse_str(source: &str) -> Result<Vec<Form>, ParseError> {
+    let tokens = lex_spanned(source)?;
+
+    Parser::with_spans(tokens, ParserConfig::default()).parse_all()
+}
+
+/// Parses every form in the tokens, wrapping them in a single `(do ...)` call.
+pub fn parse_program_as_do(tokens: Vec<Token>) -> Result<Form, ParseError> {
+    let head = Symbol { head: "do".to_string(), tail: vec![], slash: false };
+
+    Parser::new(tokens).parse_program(head)
+}
+
+/// Returns whether the source holds complete forms, so it's ready to be parsed.
+///
+/// The source is incomplete when a bracket or a string is left open, or when it ends with
+/// a reader macro waiting for its form. Sources with other errors are complete, even when
+/// the error is at the end, so that parsing them reports the error.
+pub fn is_complete(source: &str) -> bool {
+    let mut lexer = Lexer::with_config(source, LexerConfig { lossless: true, ..Default::default() });
+    let mut depth = 0usize;
+    let mut pending = false;
+    // Lossless tokens cover the whole source, so the next token starts where the last one ended
+    let mut start = 0;
+
+    while let Some(token) = lexer.next_spanned() {
+        let token = match token {
+            Ok((token, span)) => {
+                start = span.range.end;
+                token
             },
+            // Only a string or verbatim symbol left open at the end can be completed by more input
+            Err(_) => return !(lexer.is_eof() && source[start..].starts_with(['"', '|'])),
+        };
+
+        match token {
+            Token::Open(_) | Token::OpenSet => depth += 1,
+            Token::Close(_) => depth = depth.saturating_sub(1),
+            Token::Whitespace(_) | Token::Comment { .. } => continue,
+            _ => {},
         }
+
+        pending = matches!(token, Token::Quote | Token::Discard | Token::ReaderConditional | Token::Tag(_));
     }
+
+    depth == 0 && !pending
 }
 
-fn parse_call(mut tokens: Peekable<IntoIter<Token>>) -> Result<(Form, Peekable<IntoIter<Token>>), String> {
-    let mut forms = Vec::new();
+/// Expands a dotted symbol into nested `(. object member)` calls.
+fn method_call(symbol: Symbol) -> Form {
+    let single = |name: String| Form::Symbol(Box::new(Symbol { head: name, tail: vec![], slash: false }));
 
-    loop {
-        match tokens.peek() {
-            None => return Err(format!("Unexpected end of input")),
-            Some(token) => match token {
-                Token::Close(')') => {
-                    tokens.next();
-                    return Ok((Form::Call(forms), tokens))
-                }, // TODO: Ban empty calls
-                Token::Close(c) => return Err(format!("Unexpected token: `{}`, expected `)`", c)),
-                _ => {
-                    let (form, tks) = parse(tokens)?;
-                    forms.push(form);
-                    tokens = tks;
-                },
+    symbol.tail.into_iter().fold(single(symbol.head), |object, member| {
+        Form::call(single(".".to_string()), [object, single(member)])
+    })
+}
+
+/// Counts the tokens starting a form directly inside each opening bracket, in the order of the brackets.
+/// This is an estimate: a discarded form is counted too.
+fn capacities(tokens: &[Token]) -> VecDeque<usize> {
+    let mut capacities = VecDeque::new();
+    let mut open = Vec::new();
+
+    for token in tokens {
+        match token {
+            Token::Close(_) => {
+                open.pop();
+                continue;
             },
+            Token::Whitespace(_) | Token::Comment { .. } | Token::Quote | Token::Discard | Token::ReaderConditional
+                | Token::Tag(_) | Token::Text(TextKind::Tag) => continue,
+            _ => {},
+        }
+
+        if let Some(&i) = open.last() {
+            capacities[i] += 1;
+        }
+
+        if let Token::Open(_) | Token::OpenSet = token {
+            open.push(capacities.len());
+            capacities.push_back(0);
         }
     }
+
+    capacities
 }
 
-fn parse_list(mut tokens: Peekable<IntoIter<Token>>) -> Result<(Form, Peekable<IntoIter<Token>>), String> {
-    let mut forms = Vec::new();
+impl Parser {
+    /// Builds a new parser from a list of tokens.
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self::with_config(tokens, ParserConfig::default())
+    }
+
+    /// Builds a new parser from a list of tokens using the given configuration.
+    pub fn with_config(tokens: Vec<Token>, config: ParserConfig) -> Self {
+        let capacities = capacities(&tokens);
 
-    loop {
-        match tokens.peek() {
-            None => return Err(format!("Unexpected end of input")),
-            Some(token) => match token {
-                Token::Close(']') => {
-                    tokens.next();
-                    return Ok((Form::List(forms), tokens));
-                },
-                Token::Close(c) => return Err(format!("Unexpected token: `{}`, expected `]`", c)),
-                _ => {
-                    let (form, tks) = parse(tokens)?;
-                    forms.push(form);
-                    tokens = tks;
+        Self { capacities, ..Self::from_parts(tokens.into_iter().peekable(), VecDeque::new(), config, FormBuilder::default()) }
+    }
+
+    /// Builds a new parser from a list of tokens and their spans, so errors report positions.
+    pub fn with_spans(tokens: Vec<(Token, Span)>, config: ParserConfig) -> Self {
+        Self::with_builder(tokens, config, FormBuilder::default())
+    }
+
+    /// Parses every remaining form like [`Parser::parse_all`], recording the comment block right before
+    /// each top level form, or each form inside a call, list or set, as its doc. A blank line between
+    /// a comment and a form keeps it from being a doc. A form with no such comment block takes the
+    /// comment after it on the same line instead. Forms inside maps, discarded forms and reader
+    /// conditionals get no docs.
+    ///
+    /// The parser must be built from tokens that keep comments, like those of [`lex_lossless`](crate::lexer::lex_lossless).
+    pub fn parse_documented(&mut self) -> Result<Vec<Documented>, ParseError> {
+        let mut forms = Vec::new();
+
+        self.doc_path = Some(Vec::new());
+        self.skip_ignored()?;
+
+        while self.has_next() {
+            let form = self.parse()?;
+
+            self.trailing = Some(FormPath::default());
+            self.skip_ignored()?;
+            forms.push(Documented { form, docs: mem::take(&mut self.docs) });
+        }
+
+        self.doc_path = None;
+
+        Ok(forms)
+    }
+
+    /// Parses every remaining form, wrapping them in a call of `head`, so a program has a single root form.
+    pub fn parse_program(&mut self, head: Symbol) -> Result<Form, ParseError> {
+        let forms = self.parse_all()?;
+
+        Ok(Form::call(Form::Symbol(Box::new(head)), forms))
+    }
+
+    /// Parses every remaining form, collecting errors instead of stopping at the first one.
+    /// After an error, the rest of the enclosing top level form is skipped, or the next form when the error
+    /// consumed nothing, like a limit being hit, so that parsing always moves on.
+    /// Parsing stops once `max_errors` errors were found, and the result is flagged as truncated.
+    pub fn parse_recovering(&mut self, max_errors: usize) -> RecoveredForms {
+        let mut recovered = RecoveredForms { forms: Vec::new(), errors: Vec::new(), truncated: false };
+
+        loop {
+            let remaining = self.tokens.len();
+
+            match self.skip_ignored() {
+                Ok(()) if !self.has_next() => break,
+                Ok(()) => match self.parse() {
+                    Ok(form) => {
+                        recovered.forms.push(form);
+                        continue;
+                    },
+                    Err(err) => recovered.errors.push(err),
                 },
-            },
+                Err(err) => recovered.errors.push(err),
+            }
+
+            if recovered.errors.len() >= max_errors {
+                recovered.truncated = self.has_next();
+                break;
+            }
+
+            if self.depth == 0 && self.tokens.len() == remaining {
+                self.skip_balanced();
+            }
+
+            while self.depth > 0 && self.skip_balanced() {}
+            self.depth = 0;
+            self.pending = None;
         }
+
+        recovered
     }
 }
 
-fn parse_map(mut tokens: Peekable<IntoIter<Token>>) -> Result<(Form, Peekable<IntoIter<Token>>), String> {
-    let mut forms = Vec::new();
+impl<B: Build> Parser<B> {
+    /// Builds a new parser from a list of tokens and their spans, which builds its forms with `build`.
+    pub fn with_builder(tokens: Vec<(Token, Span)>, config: ParserConfig, build: B) -> Self {
+        let (tokens, spans): (Vec<_>, _) = tokens.into_iter().unzip();
+        let capacities = capacities(&tokens);
+
+        Self { capacities, ..Self::from_parts(tokens.into_iter().peekable(), spans, config, build) }
+    }
+
+    /// Builds a parser in its initial state, without presized collections.
+    fn from_parts(tokens: Peekable<IntoIter<Token>>, spans: VecDeque<Span>, config: ParserConfig, build: B) -> Self {
+        Self {
+            tokens,
+            spans,
+            config,
+            build,
+            depth: 0,
+            pending: None,
+            nesting: 0,
+            nodes: 0,
+            capacities: VecDeque::new(),
+            opened_capacity: 0,
+            doc_path: None,
+            doc: None,
+            docs: Vec::new(),
+            trailing: None,
+        }
+    }
+
+    /// Consumes the next token, along with its span when known.
+    fn next_token(&mut self) -> Option<(Token, Option<Span>)> {
+        let token = self.tokens.next()?;
+
+        match token {
+            Token::Open(_) | Token::OpenSet => {
+                self.depth += 1;
+                self.opened_capacity = self.capacities.pop_front().unwrap_or_default();
+            },
+            Token::Close(_) => self.depth = self.depth.saturating_sub(1),
+            _ => {},
+        }
+
+        Some((token, self.spans.pop_front()))
+    }
+
+    /// Skips the tokens of the next form without parsing it: a single token, or an opening bracket
+    /// along with every token up to the closing bracket that brings the depth back, whatever its kind.
+    /// A closing bracket is skipped on its own, leaving the form it closes.
+    /// Returns `false` when there's nothing left to skip.
+    fn skip_balanced(&mut self) -> bool {
+        let Some((token, _)) = self.next_token() else {
+            return false;
+        };
+        let mut depth = usize::from(matches!(token, Token::Open(_) | Token::OpenSet));
+
+        while depth > 0 {
+            match self.next_token() {
+                Some((Token::Open(_) | Token::OpenSet, _)) => depth += 1,
+                Some((Token::Close(_), _)) => depth -= 1,
+                Some(_) => {},
+                None => break,
+            }
+        }
+
+        true
+    }
+
+    /// Returns the position of the next token, when known.
+    fn peek_position(&self) -> Option<Position> {
+        self.spans.front().map(|span| span.start)
+    }
+
+    /// Builds an error at the position of the next token.
+    fn error(&self, message: String) -> ParseError {
+        self.error_at(self.peek_position(), message)
+    }
+
+    /// Builds an error at the given position.
+    fn error_at(&self, position: Option<Position>, message: String) -> ParseError {
+        ParseError::new(position, message)
+    }
+
+    /// Returns whether there's a token or a pending form left to parse. Ignored tokens count too,
+    /// so call `skip_ignored` first.
+    fn has_next(&self) -> bool {
+        self.pending.is_some() || self.tokens.len() > 0
+    }
+
+    /// Parses every remaining form until the end of the input.
+    pub fn parse_all(&mut self) -> Result<Vec<B::Form>, ParseError> {
+        let mut forms = Vec::new();
+
+        self.skip_ignored()?;
+
+        while self.has_next() {
+            forms.push(self.parse()?);
+            self.skip_ignored()?;
+        }
+
+        Ok(forms)
+    }
+
+    /// Runs `parse` without recording docs, for forms that aren't read at their own path.
+    fn without_docs<T>(&mut self, parse: impl FnOnce(&mut Self) -> T) -> T {
+        let path = self.doc_path.take();
+        let result = parse(self);
+        self.doc_path = path;
+
+        result
+    }
+
+    /// Parses the next form.
+    pub fn parse(&mut self) -> Result<B::Form, ParseError> {
+        if self.nesting >= self.config.max_depth {
+            return Err(self.error(format!("Forms are nested deeper than the limit of {} levels", self.config.max_depth)));
+        }
+
+        self.nodes += 1;
+
+        if let Some(max_nodes) = self.config.max_nodes.filter(|&max| self.nodes > max) {
+            return Err(self.error(format!("The input has more forms than the limit of {}", max_nodes)));
+        }
+
+        self.nesting += 1;
+        let form = self.parse_form();
+        self.nesting -= 1;
+
+        form
+    }
+
+    /// Parses the next form, without checking how deep it is.
+    fn parse_form(&mut self) -> Result<B::Form, ParseError> {
+        self.skip_ignored()?;
 
-    loop {
-        match tokens.peek() {
-            None => return Err(format!("Unexpected end of input")),
-            Some(token) => match token {
-                Token::Close('}') => {
-                    tokens.next();
-                    return Ok((Form::Map(forms), tokens));
+        if let Some(form) = self.pending.take() {
+            return Ok(form);
+        }
+
+        if let (Some(path), Some(doc)) = (&self.doc_path, self.doc.take()) {
+            self.docs.push((FormPath(path.clone()), doc));
+        }
+
+        self.trailing = None;
+
+        let (token, span) = self.next_token()
+            .ok_or_else(|| self.error("Unexpected end of input".to_string()))?;
+        let position = span.as_ref().map(|span| span.start);
+
+        match token {
+            Token::Open('(') => self.parse_call(position),
+            Token::Open('[') => self.parse_sequence(']').map(|forms| self.build.list(forms)),
+            Token::Open('{') => self.without_docs(Self::parse_map),
+            Token::OpenSet => self.parse_sequence('}').map(|forms| self.build.set(forms)),
+            Token::Quote => self.parse().map(|form| self.build.quote(form)),
+            tag @ (Token::Tag(_) | Token::Text(TextKind::Tag)) => self.parse_tagged(tag, span),
+            string @ (Token::String(_) | Token::Text(TextKind::String)) if self.config.concat_strings => self.parse_strings(string, span),
+            Token::Close(c) => Err(self.error_at(position, format!("Unexpected closing bracket `{}` with no matching opener", c))
+                .with_hint(format!("remove the `{}`", c))
+                .with_fix(Fix::Delete { position, token: Token::Close(c) })),
+            Token::Error(err) => Err((*err).into()),
+            token => self.build.atom(token, span.as_ref(), &self.config).map_err(|err| self.error_at(position, err)),
+        }
+    }
+
+    /// Parses the value of a tagged literal after its tag.
+    fn parse_tagged(&mut self, tag: Token, span: Option<Span>) -> Result<B::Form, ParseError> {
+        let value = self.parse()?;
+
+        self.build.tagged(tag, span.as_ref(), value).map_err(|err| self.error_at(span.map(|span| span.start), err))
+    }
+
+    /// Joins the string literal `string` with the string literals right after it.
+    fn parse_strings(&mut self, mut string: Token, span: Option<Span>) -> Result<B::Form, ParseError> {
+        let position = span.as_ref().map(|span| span.start);
+
+        while let Some((next, next_span)) = self.next_string() {
+            match (&mut string, next) {
+                (Token::String(s), Token::String(next)) => s.push_str(&next),
+                _ => return Err(self.error_at(next_span.map(|span| span.start), "Strings left in the source can't be joined".to_string())),
+            }
+        }
+
+        self.build.atom(string, span.as_ref(), &self.config).map_err(|err| self.error_at(position, err))
+    }
+
+    /// Consumes the next string literal, with its span when known, when only whitespace and comments come before it.
+    /// Those are read as docs, with a comment on the line of the string being its doc.
+    fn next_string(&mut self) -> Option<(Token, Option<Span>)> {
+        if let Some(path) = &self.doc_path {
+            self.trailing = Some(FormPath(path.clone()));
+        }
+
+        while let Some(Token::Whitespace(_) | Token::Comment { .. }) = self.tokens.peek() {
+            if let Some((token, _)) = self.next_token() {
+                self.read_doc(token);
+            }
+        }
+
+        match self.tokens.peek() {
+            Some(Token::String(_) | Token::Text(TextKind::String)) => self.next_token(),
+            _ => None,
+        }
+    }
+
+    /// Consumes the next token if it closes a form opened with the bracket matching `close`.
+    /// Errors on any other closing bracket.
+    fn eat_close(&mut self, close: char) -> Result<bool, ParseError> {
+        self.skip_ignored()?;
+
+        if self.pending.is_some() {
+            return Ok(false);
+        }
+
+        match self.tokens.peek() {
+            None => Err(self.error(format!("Unexpected end of input, expected `{}`", close))
+                .with_hint(format!("add a `{}` to close the form", close))
+                .with_fix(Fix::Insert { position: None, token: Token::Close(close) })),
+            Some(Token::Close(c)) if *c == close || self.config.brackets == BracketMode::Lenient => {
+                trace!("matched close bracket `{}`", c);
+                self.next_token();
+                self.doc = None;
+                Ok(true)
+            },
+            Some(&Token::Close(c)) => {
+                let position = self.peek_position();
+
+                Err(self.error_at(position, format!("Unexpected token: `{}`, expected `{}`", c, close))
+                    .with_hint(format!("replace the `{}` with `{}`", c, close))
+                    .with_fix(Fix::Delete { position, token: Token::Close(c) })
+                    .with_fix(Fix::Insert { position, token: Token::Close(close) }))
+            },
+            Some(_) => Ok(false),
+        }
+    }
+
+    /// Skips whitespace and comment tokens, and parses and drops every form following a discard marker `#_`.
+    /// Reader conditionals are read here too, since they may read as nothing.
+    fn skip_ignored(&mut self) -> Result<(), ParseError> {
+        while self.pending.is_none() {
+            match self.tokens.peek() {
+                Some(Token::Whitespace(_) | Token::Comment { .. }) => {
+                    if let Some((token, _)) = self.next_token() {
+                        self.read_doc(token);
+                    }
+                },
+                Some(Token::Discard) => {
+                    trace!("discarding the next form");
+                    self.next_token();
+                    self.without_docs(Self::parse)?;
                 },
-                Token::Close(c) => return Err(format!("Unexpected token: `{}`, expected `}}`", c)),
-                _ => {
-                    let (key, tks) = parse(tokens)?;
-                    let (value, tks) = parse(tks)?;
-                    forms.push((key, value));
-                    tokens = tks;
+                Some(Token::ReaderConditional) => {
+                    self.next_token();
+                    self.pending = self.without_docs(Self::parse_conditional)?;
                 },
+                _ => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds a comment line to the comment block, or to the docs when it trails a form, and drops
+    /// the block at a blank line, when recording docs.
+    fn read_doc(&mut self, token: Token) {
+        if self.doc_path.is_none() {
+            return;
+        }
+
+        match token {
+            Token::Comment { level, text } if level > 0 => {
+                let line = text.strip_prefix(' ').unwrap_or(&text).trim_end();
+
+                match (self.trailing.take(), &mut self.doc) {
+                    (Some(path), _) => self.docs.push((path, line.to_string())),
+                    (None, Some(doc)) => {
+                        doc.push('\n');
+                        doc.push_str(line);
+                    },
+                    (None, None) => self.doc = Some(line.to_string()),
+                }
+            },
+            Token::Whitespace(s) if s.contains('\n') => {
+                self.trailing = None;
+
+                if s.matches('\n').count() > 1 {
+                    self.doc = None;
+                }
             },
+            _ => {},
+        }
+    }
+
+    /// Parses the feature/form pairs of a reader conditional after its `#?`,
+    /// returning the form of the first active feature.
+    fn parse_conditional(&mut self) -> Result<Option<B::Form>, ParseError> {
+        trace!("entering parse_conditional");
+
+        if !matches!(self.tokens.peek(), Some(Token::Open('('))) {
+            return Err(self.error("Expected `(` after the reader conditional `#?`".to_string()));
+        }
+
+        self.next_token();
+
+        let mut selected = None;
+
+        while !self.eat_close(')')? {
+            let position = self.peek_position();
+            let feature = self.parse()?;
+            let Some(feature) = B::keyword(&feature) else {
+                return Err(self.error_at(position, format!("A reader conditional feature must be a keyword, found {}", B::kind(&feature).describe())));
+            };
+
+            self.skip_ignored()?;
+
+            if self.pending.is_none() && matches!(self.tokens.peek(), Some(Token::Close(_))) {
+                return Err(self.error(format!("The reader conditional feature `:{}` has no form", feature))
+                    .with_hint("add the form to read when the feature is active".to_string())
+                    .with_fix(Fix::Insert { position: self.peek_position(), token: Token::Nil }));
+            }
+
+            let form = self.parse()?;
+
+            if selected.is_none() && self.config.features.iter().any(|active| active.as_str() == feature) {
+                trace!("selected reader conditional feature `:{}`", feature);
+                selected = Some(form);
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Parses forms until the closing bracket `close`, which is consumed.
+    fn parse_sequence(&mut self, close: char) -> Result<Vec<B::Form>, ParseError> {
+        trace!("entering parse_sequence until `{}`", close);
+
+        let mut forms = Vec::with_capacity(mem::take(&mut self.opened_capacity));
+
+        while !self.eat_close(close)? {
+            if let Some(path) = &mut self.doc_path {
+                path.push(PathSegment::Index(forms.len()));
+            }
+
+            let form = self.parse();
+
+            if let Some(path) = &mut self.doc_path {
+                self.trailing = Some(FormPath(path.clone()));
+                path.pop();
+            }
+
+            forms.push(form?);
+        }
+
+        Ok(forms)
+    }
+
+    /// Parses a call opened at `position`, rejecting `()` unless empty calls are allowed.
+    fn parse_call(&mut self, position: Option<Position>) -> Result<B::Form, ParseError> {
+        trace!("entering parse_call");
+
+        let forms = self.parse_sequence(')')?;
+
+        if forms.is_empty() && !self.config.allow_empty_call {
+            return Err(self.error_at(position, "An empty call `()` has nothing to call".to_string())
+                .with_hint("use `nil` or an empty list `[]` instead".to_string()));
+        }
+
+        Ok(self.build.call(forms))
+    }
+
+    fn parse_map(&mut self) -> Result<B::Form, ParseError> {
+        trace!("entering parse_map");
+
+        let mut forms = Vec::with_capacity(mem::take(&mut self.opened_capacity) / 2);
+
+        while !self.eat_close('}')? {
+            let position = self.peek_position();
+            let key = self.parse()?;
+
+            let kind = B::kind(&key);
+
+            if !self.config.allow_compound_keys && matches!(kind, FormKind::Map | FormKind::Call | FormKind::Set) {
+                return Err(self.error_at(position, format!("A map key can't be {}", kind.describe())));
+            }
+
+            self.skip_ignored()?;
+
+            if self.pending.is_none() && matches!(self.tokens.peek(), Some(Token::Close(_))) {
+                return Err(self.error("A map needs an even number of forms, but the last key has no value. \
+                    Add a value for it, or use a set literal `#{...}` if you meant a set".to_string())
+                    .with_hint("add a value after the last key".to_string())
+                    .with_fix(Fix::Insert { position: self.peek_position(), token: Token::Nil }));
+            }
+
+            let value = self.parse()?;
+            forms.push((key, value));
+        }
+
+        Ok(self.build.map(forms))
+    }
+}
+
+impl Build for FormBuilder {
+    type Form = Form;
+
+    fn atom(&mut self, token: Token, _: Option<&Span>, config: &ParserConfig) -> Result<Form, String> {
+        match token {
+            Token::Symbol(symbol) if config.dot_as_method_call && !symbol.tail.is_empty() && !symbol.slash => Ok(method_call(*symbol)),
+            Token::String(s) => Ok(Form::String(self.string(s, config.intern_strings))),
+            token => Form::try_from(token),
+        }
+    }
+
+    fn tagged(&mut self, tag: Token, _: Option<&Span>, value: Form) -> Result<Form, String> {
+        match tag {
+            Token::Tag(tag) => Ok(Form::Tagged { tag, value: Box::new(value) }),
+            tag => Err(format!("Unexpected token: `{}`, expected a tag", tag)),
+        }
+    }
+
+    fn call(&mut self, forms: Vec<Form>) -> Form {
+        Form::Call(forms)
+    }
+
+    fn list(&mut self, forms: Vec<Form>) -> Form {
+        Form::List(forms)
+    }
+
+    fn map(&mut self, entries: Vec<(Form, Form)>) -> Form {
+        Form::Map(entries)
+    }
+
+    fn set(&mut self, forms: Vec<Form>) -> Form {
+        Form::Set(forms)
+    }
+
+    fn quote(&mut self, form: Form) -> Form {
+        form.quote()
+    }
+
+    fn kind(form: &Form) -> FormKind {
+        form.kind()
+    }
+
+    fn keyword(form: &Form) -> Option<&str> {
+        match form {
+            Form::Keyword(keyword) => Some(keyword.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl FormBuilder {
+    /// Turns a string literal into its shared form, reusing an equal one when interning strings.
+    fn string(&mut self, s: String, intern: bool) -> Arc<str> {
+        if !intern {
+            return s.into();
+        }
+
+        if let Some(interned) = self.strings.get(s.as_str()) {
+            return interned.clone();
+        }
+
+        let interned: Arc<str> = s.into();
+        self.strings.insert(interned.clone());
+
+        interned
+    }
+}
+
+impl FormKind {
+    /// Returns a short description of the kind of form, for error messages.
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            FormKind::Call => "a call",
+            FormKind::Symbol => "a symbol",
+            FormKind::Float => "a float",
+            FormKind::Integer => "an integer",
+            #[cfg(feature = "complex")]
+            FormKind::Complex => "a complex number",
+            FormKind::String => "a string",
+            FormKind::Char => "a char",
+            FormKind::Keyword => "a keyword",
+            FormKind::Bool => "a bool",
+            FormKind::Nil => "nil",
+            FormKind::List => "a list",
+            FormKind::Map => "a map",
+            FormKind::Set => "a set",
+            FormKind::Quote => "a quote",
+            FormKind::Tagged => "a tagged literal",
+        }
+    }
+}
+
+impl Form {
+    /// Returns the variant of the form.
+    pub fn kind(&self) -> FormKind {
+        match self {
+            Form::Call(_) => FormKind::Call,
+            Form::Symbol(_) => FormKind::Symbol,
+            Form::Float(_) => FormKind::Float,
+            Form::Integer(_) => FormKind::Integer,
+            #[cfg(feature = "complex")]
+            Form::Complex { .. } => FormKind::Complex,
+            Form::String(_) => FormKind::String,
+            Form::Char(_) => FormKind::Char,
+            Form::Keyword(_) => FormKind::Keyword,
+            Form::Bool(_) => FormKind::Bool,
+            Form::Nil => FormKind::Nil,
+            Form::List(_) => FormKind::List,
+            Form::Map(_) => FormKind::Map,
+            Form::Set(_) => FormKind::Set,
+            Form::Quote(_) => FormKind::Quote,
+            Form::Tagged { .. } => FormKind::Tagged,
+        }
+    }
+
+    /// Returns whether the form is an atom: a number, string, char, keyword, bool, nil or symbol.
+    pub fn is_atom(&self) -> bool {
+        !self.is_collection() && !self.is_quoted() && !matches!(self, Form::Tagged { .. })
+    }
+
+    /// Returns whether the form is a `Call`, `List`, `Map` or `Set`.
+    pub fn is_collection(&self) -> bool {
+        matches!(self, Form::Call(_) | Form::List(_) | Form::Map(_) | Form::Set(_))
+    }
+
+    /// Returns the opening bracket a collection is written with: `(`, `[`, `{` or `#{`, `None` for other forms.
+    ///
+    /// The opening bracket decides the kind of collection in every [`BracketMode`], so it's known even when
+    /// a lenient parser accepted another kind of closing bracket.
+    pub fn open_bracket(&self) -> Option<&'static str> {
+        match self {
+            Form::Call(_) => Some("("),
+            Form::List(_) => Some("["),
+            Form::Map(_) => Some("{"),
+            Form::Set(_) => Some("#{"),
+            _ => None,
         }
     }
-}
\ No newline at end of file
+
+    /// Returns a short description of the kind of form, for error messages.
+    pub(crate) fn describe(&self) -> &'static str {
+        self.kind().describe()
+    }
+
+    /// Builds a `List`, the form of `[...]`, from the given forms.
+    pub fn list(forms: impl IntoIterator<Item = Form>) -> Form {
+        Form::List(forms.into_iter().collect())
+    }
+
+    /// Builds a `Set`, the form of `#{...}`, from the given forms.
+    pub fn set(forms: impl IntoIterator<Item = Form>) -> Form {
+        Form::Set(forms.into_iter().collect())
+    }
+
+    /// Builds a `Call` of `head` with the given arguments.
+    pub fn call(head: Form, args: impl IntoIterator<Item = Form>) -> Form {
+        Form::Call(std::iter::once(head).chain(args).collect())
+    }
+
+    /// Wraps the form in a quote.
+    pub fn quote(self) -> Form {
+        Form::Quote(Box::new(self))
+    }
+
+    /// Returns whether the form is quoted.
+    pub fn is_quoted(&self) -> bool {
+        matches!(self, Form::Quote(_))
+    }
+
+    /// Returns the number of children of a `Call`, `List` or `Set`, `None` for other forms.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Form::Call(forms) | Form::List(forms) | Form::Set(forms) => Some(forms.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a `Call`, `List` or `Set` has no children, `None` for other forms.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Returns the `i`-th child of a `Call`, `List` or `Set`, in source order.
+    /// Returns `None` for other forms or when `i` is out of range.
+    pub fn get(&self, i: usize) -> Option<&Form> {
+        match self {
+            Form::Call(forms) | Form::List(forms) | Form::Set(forms) => forms.get(i),
+            _ => None,
+        }
+    }
+
+    /// Returns the direct sub-forms: the children of a `Call`, `List` or `Set`,
+    /// the keys and values of a `Map` in order, and the inner form of a `Quote` or the value of a `Tagged`.
+    pub fn children(&self) -> Vec<&Form> {
+        match self {
+            Form::Call(forms) | Form::List(forms) | Form::Set(forms) => forms.iter().collect(),
+            Form::Map(entries) => entries.iter().flat_map(|(key, value)| [key, value]).collect(),
+            Form::Quote(form) | Form::Tagged { value: form, .. } => vec![form],
+            _ => vec![],
+        }
+    }
+
+    /// Returns a lazy iterator over the form and all its descendants, in pre-order.
+    pub fn depth_first_iter(&self) -> DepthFirst<'_> {
+        DepthFirst::new(self)
+    }
+
+    /// Applies `f` to each direct sub-form, as listed by [`Form::children`], keeping the form's shape.
+    pub fn map_forms(self, mut f: impl FnMut(Form) -> Form) -> Form {
+        match self {
+            Form::Call(forms) => Form::Call(forms.into_iter().map(f).collect()),
+            Form::List(forms) => Form::List(forms.into_iter().map(f).collect()),
+            Form::Set(forms) => Form::Set(forms.into_iter().map(f).collect()),
+            Form::Map(entries) => Form::Map(entries.into_iter().map(|(key, value)| (f(key), f(value))).collect()),
+            Form::Quote(form) => Form::Quote(Box::new(f(*form))),
+            Form::Tagged { tag, value } => Form::Tagged { tag, value: Box::new(f(*value)) },
+            form => form,
+        }
+    }
+
+    /// Returns the owned children of a `Call`, `List` or `Set`, `None` for other forms.
+    pub fn into_children(self) -> Option<Vec<Form>> {
+        match self {
+            Form::Call(forms) | Form::List(forms) | Form::Set(forms) => Some(forms),
+            _ => None,
+        }
+    }
+
+    /// Splits a `Call` into its owned head and the forms after it, `None` for other forms and empty calls.
+    pub fn into_call_parts(self) -> Option<(Form, Vec<Form>)> {
+        match self {
+            Form::Call(mut forms) if !forms.is_empty() => {
+                let body = forms.split_off(1);
+
+                Some((forms.pop()?, body))
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the owned key/value pairs of a `Map`, `None` for other forms.
+    pub fn into_map_entries(self) -> Option<Vec<(Form, Form)>> {
+        match self {
+            Form::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Builds a `Map` whose keys are the keywords in `entries`.
+    pub fn keyword_map(entries: Vec<(Keyword, Form)>) -> Form {
+        Form::Map(entries.into_iter()
+            .map(|(keyword, value)| (Form::Keyword(keyword), value))
+            .collect())
+    }
+
+    /// Returns the name/value pairs of a `Map` whose keys are all keywords.
+    /// Returns `None` for other forms or when any key isn't a keyword.
+    pub fn as_keyword_map(&self) -> Option<Vec<(&str, &Form)>> {
+        self.map_entries()?
+            .map(|(key, value)| match key {
+                Form::Keyword(keyword) => Some((keyword.as_str(), value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Builds a `Char` from a code point, or `None` if it isn't a valid `char`.
+    pub fn char_from_u32(code: u32) -> Option<Form> {
+        char::from_u32(code).map(Form::Char)
+    }
+
+    /// Builds a `Keyword`, checking its name like [`Keyword::new`] and that it lexes back as the same keyword.
+    pub fn try_new_keyword(name: &str) -> Result<Form, String> {
+        let form = Keyword::new(name).map(Form::Keyword)?;
+
+        form.check_atom().map(|()| form)
+    }
+
+    /// Builds a `Char`, checking that it's written in a way that lexes back as the same char,
+    /// which isn't the case for whitespace other than space, tab and line breaks.
+    pub fn try_new_char(c: char) -> Result<Form, String> {
+        let form = Form::Char(c);
+
+        form.check_atom().map(|()| form)
+    }
+
+    /// Checks that the form and everything in it is written by `Display` in a way that lexes back
+    /// as the same form with the default configuration, returning an error for the first atom or
+    /// tag that doesn't. Forms built by hand, like a keyword converted to `camelCase`, may fail it.
+    pub fn validate_constructible(&self) -> Result<(), String> {
+        self.depth_first_iter().try_for_each(|form| match form {
+            Form::Tagged { tag, .. } => match lex(&format!("#{}", tag)).as_deref() {
+                Ok([Token::Tag(lexed)]) if lexed == tag => Ok(()),
+                _ => Err(format!("The tag `#{}` doesn't lex back as itself", tag)),
+            },
+            form if form.is_atom() => form.check_atom(),
+            _ => Ok(()),
+        })
+    }
+
+    /// Checks that an atom lexes back as itself.
+    fn check_atom(&self) -> Result<(), String> {
+        let text = self.to_string();
+        let relexed = match lex(&text).as_deref() {
+            Ok([token]) => Form::try_from(token.clone()).ok(),
+            _ => None,
+        };
+
+        match relexed {
+            Some(form) if form == *self => Ok(()),
+            Some(Form::Float(a)) if a.is_nan() && matches!(self, Form::Float(b) if b.is_nan()) => Ok(()),
+            _ => Err(format!("Writing {} as `{}` doesn't lex back as the same form", self.describe(), text)),
+        }
+    }
+
+    /// Returns the code point of a `Char`, `None` for other forms.
+    pub fn char_value(&self) -> Option<u32> {
+        match self {
+            Form::Char(c) => Some(*c as u32),
+            _ => None,
+        }
+    }
+
+    /// Turns a `Map` into an [`IndexedMap`] for fast lookups, `None` for other forms.
+    pub fn index_map(self) -> Option<IndexedMap> {
+        let entries = self.into_map_entries()?;
+        let mut index = HashMap::with_capacity(entries.len());
+
+        for (i, (key, _)) in entries.iter().enumerate() {
+            index.entry(key.clone()).or_insert(i);
+        }
+
+        Some(IndexedMap { entries, index })
+    }
+
+    /// Returns the symbol being called by a `Call`, with its dotted parts in `head` and `tail`.
+    /// Returns `None` for other forms or when the head of the call isn't a symbol.
+    pub fn callee_symbol(&self) -> Option<&Symbol> {
+        match self {
+            Form::Call(forms) => match forms.first() {
+                Some(Form::Symbol(symbol)) => Some(symbol),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns an iterator over the key/value pairs of a `Map`, `None` for other forms.
+    pub fn map_entries(&self) -> Option<impl Iterator<Item = (&Form, &Form)>> {
+        match self {
+            Form::Map(entries) => Some(entries.iter().map(|(key, value)| (key, value))),
+            _ => None,
+        }
+    }
+
+    /// Returns the kind shared by every key of a `Map`, like [`FormKind::Keyword`] when all keys are keywords.
+    /// Returns `None` when the keys are of mixed kinds, for an empty map and for other forms.
+    pub fn key_kind(&self) -> Option<FormKind> {
+        let mut kinds = self.map_entries()?.map(|(key, _)| key.kind());
+        let kind = kinds.next()?;
+
+        kinds.all(|other| other == kind).then_some(kind)
+    }
+
+    /// Returns the value for `key` in a `Map`, looking it up linearly.
+    /// Returns `None` for other forms or when the key is absent.
+    pub fn map_get(&self, key: &Form) -> Option<&Form> {
+        self.map_entries()?
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Sets `key` to `value` in a `Map`, updating an existing key in place or adding it at the end.
+    /// Other forms are returned unchanged.
+    pub fn assoc(self, key: Form, value: Form) -> Form {
+        match self {
+            Form::Map(mut entries) => {
+                match entries.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, v)) => *v = value,
+                    None => entries.push((key, value)),
+                }
+
+                Form::Map(entries)
+            },
+            form => form,
+        }
+    }
+
+    /// Removes `key` from a `Map`, keeping the order of the other entries.
+    /// Other forms are returned unchanged.
+    pub fn dissoc(self, key: &Form) -> Form {
+        match self {
+            Form::Map(mut entries) => {
+                entries.retain(|(k, _)| k != key);
+
+                Form::Map(entries)
+            },
+            form => form,
+        }
+    }
+
+    /// Sets every entry of the `other` map in this `Map`, as [`Form::assoc`] does, so `other` wins on shared keys.
+    /// When either form isn't a map, this one is returned unchanged.
+    pub fn merge(self, other: Form) -> Form {
+        match (self, other) {
+            (form @ Form::Map(_), Form::Map(entries)) => entries.into_iter()
+                .fold(form, |form, (key, value)| form.assoc(key, value)),
+            (form, _) => form,
+        }
+    }
+
+    /// Returns the descendant found by following `path`, or `None` if any step doesn't exist.
+    pub fn get_path(&self, path: &FormPath) -> Option<&Form> {
+        path.0.iter().try_fold(self, |form, segment| match segment {
+            PathSegment::Index(i) => form.get(*i),
+            PathSegment::MapKey(key) => form.map_get(key),
+        })
+    }
+
+    /// Mutable version of [`Form::get_path`].
+    pub fn get_path_mut(&mut self, path: &FormPath) -> Option<&mut Form> {
+        path.0.iter().try_fold(self, |form, segment| match (form, segment) {
+            (Form::Call(forms) | Form::List(forms) | Form::Set(forms), PathSegment::Index(i)) => forms.get_mut(*i),
+            (Form::Map(entries), PathSegment::MapKey(key)) => entries.iter_mut()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value),
+            _ => None,
+        })
+    }
+
+    /// Strips one quote layer. Unquoted forms are returned unchanged.
+    pub fn unquote_once(self) -> Form {
+        match self {
+            Form::Quote(form) => *form,
+            form => form,
+        }
+    }
+
+    /// Compares two forms like `==`, but matches symbols and keywords by their [name](Symbol::name),
+    /// ignoring any namespace. So `math.sqrt` matches `sqrt` and `:ns/foo` matches `:foo`.
+    ///
+    /// Unlike [`PartialEq`], this isn't an identity check: forms that read differently can match.
+    pub fn eq_unqualified(&self, other: &Form) -> bool {
+        fn all_eq(a: &[Form], b: &[Form]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_unqualified(b))
+        }
+
+        match (self, other) {
+            (Form::Symbol(a), Form::Symbol(b)) => a.name() == b.name(),
+            (Form::Keyword(a), Form::Keyword(b)) => a.name() == b.name(),
+            (Form::Call(a), Form::Call(b)) | (Form::List(a), Form::List(b)) | (Form::Set(a), Form::Set(b)) => all_eq(a, b),
+            (Form::Map(a), Form::Map(b)) => a.len() == b.len()
+                && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka.eq_unqualified(kb) && va.eq_unqualified(vb)),
+            (Form::Quote(a), Form::Quote(b)) => a.eq_unqualified(b),
+            (Form::Tagged { tag: ta, value: a }, Form::Tagged { tag: tb, value: b }) => ta.name() == tb.name() && a.eq_unqualified(b),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Compares two forms like `==`, but treats a `List`, a `Call` and a quoted `Call` with equal
+    /// elements as the same sequence, so `[1 [2]]` matches `'(1 (2))`. Other collections still
+    /// have to be of the same kind.
+    pub fn seq_eq(&self, other: &Form) -> bool {
+        fn all_eq(a: &[Form], b: &[Form]) -> bool {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.seq_eq(b))
+        }
+
+        fn as_seq(form: &Form) -> Option<&[Form]> {
+            match form {
+                Form::List(forms) | Form::Call(forms) => Some(forms),
+                Form::Quote(form) => match &**form {
+                    Form::Call(forms) => Some(forms),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        if let (Some(a), Some(b)) = (as_seq(self), as_seq(other)) {
+            return all_eq(a, b);
+        }
+
+        match (self, other) {
+            (Form::Set(a), Form::Set(b)) => all_eq(a, b),
+            (Form::Map(a), Form::Map(b)) => a.len() == b.len()
+                && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka.seq_eq(kb) && va.seq_eq(vb)),
+            (Form::Quote(a), Form::Quote(b)) => a.seq_eq(b),
+            (Form::Tagged { tag: ta, value: a }, Form::Tagged { tag: tb, value: b }) => ta == tb && a.seq_eq(b),
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl ParseError {
+    /// Renders the error along with the line of `source` where it happened, when its position is known.
+    pub fn render(&self, source: &str) -> String {
+        self.render_styled(source, &Style::PLAIN)
+    }
+
+    /// Like [`ParseError::render`], with ANSI colors for a terminal.
+    #[cfg(feature = "colored")]
+    pub fn render_colored(&self, source: &str) -> String {
+        self.render_styled(source, &Style::COLORED)
+    }
+
+    fn render_styled(&self, source: &str, style: &Style) -> String {
+        match (self.position, &self.file) {
+            (Some(position), file) => render_styled(source, file.as_deref(), position, &self.message, style),
+            (None, Some(file)) => format!("{} in {}", style.header(&self.message), file),
+            (None, None) => style.header(&self.message),
+        }
+    }
+
+    /// Returns the edits that would fix the error, empty when there's no obvious fix.
+    pub fn suggestions(&self) -> Vec<Fix> {
+        self.fixes.clone()
+    }
+
+    fn new(position: Option<Position>, message: String) -> Self {
+        ParseError {
+            message,
+            position,
+            file: None,
+            hint: None,
+            fixes: Vec::new(),
+        }
+    }
+
+    fn with_hint(self, hint: String) -> Self {
+        ParseError { hint: Some(hint), ..self }
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Self {
+        self.fixes.push(fix);
+        self
+    }
+
+    /// Attaches the name of the file the error was found in.
+    pub fn in_file(self, file: &str) -> Self {
+        ParseError { file: Some(file.to_string()), ..self }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        ParseError {
+            message: err.message,
+            position: Some(err.position),
+            file: err.file,
+            hint: None,
+            fixes: Vec::new(),
+        }
+    }
+}
+
+impl IndexedMap {
+    /// Returns the value for `key`.
+    pub fn get(&self, key: &Form) -> Option<&Form> {
+        self.index.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    /// Returns whether the map has `key`.
+    pub fn contains_key(&self, key: &Form) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Returns an iterator over the key/value pairs in their original order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Form, &Form)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    /// Returns the number of entries, counting repeated keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Forms are compared structurally, so this doesn't hold for `Float(NaN)`,
+/// which is never equal to itself and so can't be found as a map key.
+impl Eq for Form {}
+
+impl Documented {
+    /// Returns the doc of the top level form.
+    pub fn doc(&self) -> Option<&str> {
+        self.doc_at(&FormPath::default())
+    }
+
+    /// Returns the doc of the form at `path` from the top level form, like [`Form::get_path`].
+    pub fn doc_at(&self, path: &FormPath) -> Option<&str> {
+        self.docs.iter()
+            .find(|(doc_path, _)| doc_path == path)
+            .map(|(_, doc)| doc.as_str())
+    }
+}
+
+/// Hashes floats by their bits, with `-0.0` hashed as `0.0` since they are equal.
+impl Hash for Form {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+
+        match self {
+            Form::Call(forms) | Form::List(forms) | Form::Set(forms) => forms.hash(state),
+            Form::Symbol(symbol) => symbol.hash(state),
+            Form::Float(f) => (if *f == 0.0 { 0.0f64 } else { *f }).to_bits().hash(state),
+            Form::Integer(i) => i.hash(state),
+            #[cfg(feature = "complex")]
+            Form::Complex { re, im } => {
+                Form::Float(*re).hash(state);
+                Form::Float(*im).hash(state);
+            },
+            Form::String(s) => s.hash(state),
+            Form::Keyword(k) => k.hash(state),
+            Form::Char(c) => c.hash(state),
+            Form::Bool(b) => b.hash(state),
+            Form::Nil => {},
+            Form::Map(entries) => entries.hash(state),
+            Form::Quote(form) => form.hash(state),
+            Form::Tagged { tag, value } => {
+                tag.hash(state);
+                value.hash(state);
+            },
+        }
+    }
+}
+
+/// Collects forms into a `List`.
+impl FromIterator<Form> for Form {
+    fn from_iter<I: IntoIterator<Item = Form>>(forms: I) -> Self {
+        Form::list(forms)
+    }
+}
+
+/// Collects key/value pairs into a `Map`, keeping their order.
+impl FromIterator<(Form, Form)> for Form {
+    fn from_iter<I: IntoIterator<Item = (Form, Form)>>(entries: I) -> Self {
+        Form::Map(entries.into_iter().collect())
+    }
+}
+
+impl Display for Form {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fn join(forms: &[Form]) -> String {
+            forms.iter().map(Form::to_string).collect::<Vec<_>>().join(" ")
+        }
+
+        let s = match self {
+            Form::Call(forms) => format!("({})", join(forms)),
+            Form::Symbol(symbol) => symbol.to_string(),
+            Form::Float(n) => format_float(*n),
+            Form::Integer(i) => i.to_string(),
+            #[cfg(feature = "complex")]
+            Form::Complex { re, im } => format_complex(*re, *im),
+            Form::String(s) => format_string(s),
+            Form::Char(c) => format_char(*c),
+            Form::Keyword(k) => k.to_string(),
+            Form::Bool(b) => b.to_string(),
+            Form::Nil => "nil".to_string(),
+            Form::List(forms) => format!("[{}]", join(forms)),
+            Form::Map(entries) => {
+                let entries: Vec<_> = entries.iter().map(|(key, value)| format!("{} {}", key, value)).collect();
+
+                format!("{{{}}}", entries.join(" "))
+            }
+            Form::Set(forms) => format!("#{{{}}}", join(forms)),
+            Form::Quote(form) => format!("'{}", form),
+            Form::Tagged { tag, value } => format!("#{} {}", tag, value),
+        };
+
+        f.pad(&s)
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (self.position, &self.file) {
+            (Some(position), file) => write!(f, "{} at {}", self.message, location(file.as_deref(), position)),
+            (None, Some(file)) => write!(f, "{} in {}", self.message, file),
+            (None, None) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl TryFrom<Token> for Form {
+    type Error = String;
+
+    /// Converts an atom token into its form. Errors on brackets and other tokens that aren't forms by themselves.
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Integer(i) => Ok(Form::Integer(i)),
+            Token::Float(f) => Ok(Form::Float(f)),
+            #[cfg(feature = "complex")]
+            Token::Complex { re, im } => Ok(Form::Complex { re, im }),
+            Token::String(s) => Ok(Form::String(s.into())),
+            Token::Char(c) => Ok(Form::Char(c)),
+            Token::Symbol(s) => Ok(Form::Symbol(s)),
+            Token::Keyword(k) => Ok(Form::Keyword(k)),
+            Token::Bool(b) => Ok(Form::Bool(b)),
+            Token::Nil => Ok(Form::Nil),
+            token => Err(format!("Unexpected token: `{}`, expected an atom", token)),
+        }
+    }
+}
+
+impl TryFrom<&Form> for i64 {
+    type Error = String;
+
+    fn try_from(form: &Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::Integer(i) => Ok(*i),
+            form => Err(format!("Expected an integer, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<Form> for i64 {
+    type Error = String;
+
+    fn try_from(form: Form) -> Result<Self, Self::Error> {
+        i64::try_from(&form)
+    }
+}
+
+/// Accepts both floats and integers.
+impl TryFrom<&Form> for f64 {
+    type Error = String;
+
+    fn try_from(form: &Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::Float(f) => Ok(*f),
+            Form::Integer(i) => Ok(*i as f64),
+            form => Err(format!("Expected a float, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<Form> for f64 {
+    type Error = String;
+
+    fn try_from(form: Form) -> Result<Self, Self::Error> {
+        f64::try_from(&form)
+    }
+}
+
+impl TryFrom<&Form> for String {
+    type Error = String;
+
+    fn try_from(form: &Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::String(s) => Ok(s.to_string()),
+            form => Err(format!("Expected a string, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<Form> for String {
+    type Error = String;
+
+    fn try_from(form: Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::String(s) => Ok(s.to_string()),
+            form => Err(format!("Expected a string, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<&Form> for char {
+    type Error = String;
+
+    fn try_from(form: &Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::Char(c) => Ok(*c),
+            form => Err(format!("Expected a char, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<Form> for char {
+    type Error = String;
+
+    fn try_from(form: Form) -> Result<Self, Self::Error> {
+        char::try_from(&form)
+    }
+}
+
+impl TryFrom<&Form> for bool {
+    type Error = String;
+
+    fn try_from(form: &Form) -> Result<Self, Self::Error> {
+        match form {
+            Form::Bool(b) => Ok(*b),
+            form => Err(format!("Expected a bool, found {}", form.describe())),
+        }
+    }
+}
+
+impl TryFrom<Form> for bool {
+    type Error = String;
+
+    fn try_from(form: Form) -> Result<Self, Self::Error> {
+        bool::try_from(&form)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use crate::lexer::{keyword::Keyword, lex, lex_lossless, lex_spanned, Lexer, LexerConfig, symbol::Symbol, token::Token, Position};
+
+    use super::{is_complete, parse, parse_program_as_do, BracketMode, Fix, Form, FormKind, FormPath, Parser, ParserConfig, PathSegment, RecoveredForms};
+
+    fn parse_str(source: &str) -> Form {
+        let tokens = lex(source).unwrap();
+        let (form, _) = parse(tokens.into_iter().peekable()).unwrap();
+        form
+    }
+
+    #[test]
+    fn constructors() {
+        assert_eq!(Form::list((1..=3).map(Form::Integer)), parse_str("[1 2 3]"));
+        assert_eq!(Form::list([]), parse_str("[]"));
+        assert_eq!(Form::set([parse_str(":a"), parse_str(":b")]), parse_str("#{:a :b}"));
+        assert_eq!(Form::call(parse_str("+"), vec![Form::Integer(1), Form::Integer(2)]), parse_str("(+ 1 2)"));
+        assert_eq!(Form::call(parse_str("f"), []), parse_str("(f)"));
+    }
+
+    #[test]
+    fn collect() {
+        assert_eq!((1..=3).map(Form::Integer).collect::<Form>(), parse_str("[1 2 3]"));
+
+        let pairs = vec![
+            (parse_str(":z"), Form::Integer(1)),
+            (parse_str(":a"), Form::Integer(2)),
+            (parse_str(":m"), Form::Integer(3)),
+        ];
+        let map = pairs.into_iter().collect::<Form>();
+
+        assert_eq!(map, parse_str("{:z 1 :a 2 :m 3}"));
+        assert_eq!(map.to_string(), "{:z 1 :a 2 :m 3}");
+    }
+
+    #[test]
+    fn quote_any_form() {
+        for source in ["[1 2 3]", "{:a 1}", "#{1}", "(f x)", ":kw", "42", "1.5", "sym", "\"s\"", "\\c", "true", "nil"] {
+            assert_eq!(parse_str(&format!("'{}", source)), parse_str(source).quote(), "quoting {}", source);
+        }
+    }
+
+    #[test]
+    fn stacked_reader_macros() {
+        let features = HashSet::from([Keyword::new("x").unwrap()]);
+        let parse_with = |source: &str| {
+            let config = ParserConfig { features: features.clone(), ..Default::default() };
+
+            Parser::with_config(lex(source).unwrap(), config).parse_all().unwrap()
+        };
+
+        assert_eq!(parse_str("''x"), parse_str("x").quote().quote());
+        assert_eq!(parse_with("'#_a b"), vec![parse_str("'b")]);
+        assert_eq!(parse_with("#_'a b"), vec![parse_str("b")]);
+        assert_eq!(parse_with("#_#_a b c"), vec![parse_str("c")]);
+        assert_eq!(parse_with("'#?(:x 1 :y 2)"), vec![parse_str("'1")]);
+        assert_eq!(parse_with("#?(:x 'a) #?(:x #_b c)"), vec![parse_str("'a"), parse_str("c")]);
+    }
+
+    #[test]
+    fn quote() {
+        let form = Form::Integer(1).quote();
+
+        assert!(form.is_quoted());
+        assert_eq!(form, Form::Quote(Box::new(Form::Integer(1))));
+        assert_eq!(form.unquote_once(), Form::Integer(1));
+        assert!(!Form::Integer(1).is_quoted());
+        assert_eq!(Form::Integer(1).unquote_once(), Form::Integer(1));
+    }
+
+    #[test]
+    fn parse_each_form() {
+        let cases = vec![
+            ("1", Form::Integer(1)),
+            ("1.5", Form::Float(1.5)),
+            ("\"s\"", Form::String("s".into())),
+            ("\\c", Form::Char('c')),
+            ("foo", Form::Symbol(Box::new(Symbol { head: "foo".to_string(), tail: vec![], slash: false }))),
+            (":kw", Form::Keyword(Keyword::new("kw").unwrap())),
+            ("true", Form::Bool(true)),
+            ("nil", Form::Nil),
+            ("(1)", Form::Call(vec![Form::Integer(1)])),
+            ("[1]", Form::List(vec![Form::Integer(1)])),
+            ("{1 2}", Form::Map(vec![(Form::Integer(1), Form::Integer(2))])),
+            ("'1", Form::Integer(1).quote()),
+        ];
+
+        for (source, expected) in cases {
+            let tokens = lex(&format!("{} rest", source)).unwrap();
+            let (form, mut rest) = parse(tokens.into_iter().peekable()).unwrap();
+
+            assert_eq!(form, expected);
+            assert!(matches!(rest.next(), Some(Token::Symbol(_))));
+            assert_eq!(rest.next(), None);
+        }
+
+        assert_eq!(parse(Vec::new().into_iter().peekable()).unwrap_err().message, "Unexpected end of input");
+    }
+
+    #[test]
+    fn docs() {
+        let source = "#!/usr/bin/env rubla
+;; Greets someone.
+;;   Twice.
+(defn foo [name]
+  ; the greeting
+  (print name) ; ignored, the form has a doc
+  {:a ; no docs in maps
+   1} ; the map
+  )
+
+;; detached
+
+(bar) ; about bar
+;; about baz
+#_(old) (baz)";
+        let forms = Parser::with_spans(lex_lossless(source).unwrap(), ParserConfig::default()).parse_documented().unwrap();
+        let path = |i| FormPath(vec![PathSegment::Index(i)]);
+
+        assert_eq!(forms.len(), 3);
+        assert_eq!(forms[0].form, parse_str("(defn foo [name] (print name) {:a 1})"));
+        assert_eq!(forms[0].doc(), Some("Greets someone.\n  Twice."));
+        assert_eq!(forms[0].doc_at(&path(1)), None);
+        assert_eq!(forms[0].doc_at(&path(3)), Some("the greeting"));
+        assert_eq!(forms[0].doc_at(&path(4)), Some("the map"));
+        assert_eq!(forms[1].doc(), Some("about bar"));
+        assert_eq!(forms[2].doc(), None);
+        assert_eq!(Parser::new(lex(";; doc\n(f)").unwrap()).parse_documented().unwrap()[0].doc(), None);
+
+        // Comments skipped while joining strings are still read as docs
+        let source = "(def s \"a\" ; the string\n  \"b\")\n;; about g\n(g \"c\" ; the last string\n)";
+        let config = ParserConfig { concat_strings: true, ..Default::default() };
+        let forms = Parser::with_spans(lex_lossless(source).unwrap(), config).parse_documented().unwrap();
+
+        assert_eq!(forms[0].doc_at(&path(2)), Some("the string"));
+        assert_eq!(forms[1].doc(), Some("about g"));
+        assert_eq!(forms[1].doc_at(&path(1)), Some("the last string"));
+    }
+
+    #[test]
+    fn comments_between_forms() {
+        let forms = Parser::new(lex("(a) ;; doc\n(b)").unwrap()).parse_all().unwrap();
+        assert_eq!(forms, vec![parse_str("(a)"), parse_str("(b)")]);
+
+        let forms = Parser::new(lex("; header\n(a ; inline\n b) [1 ; one\n 2] ; at eof").unwrap()).parse_all().unwrap();
+        assert_eq!(forms, vec![parse_str("(a b)"), parse_str("[1 2]")]);
+
+        let forms = Parser::new(lex("{:a ; key\n 1};").unwrap()).parse_all().unwrap();
+        assert_eq!(forms, vec![parse_str("{:a 1}")]);
+
+        let forms = Parser::new(lex(";; nothing but comments").unwrap()).parse_all().unwrap();
+        assert_eq!(forms, vec![]);
+    }
+
+    #[test]
+    fn lossless_tokens() {
+        let source = "(a ; inline\n b) ; at eof";
+        let forms = Parser::with_spans(lex_lossless(source).unwrap(), ParserConfig::default()).parse_all().unwrap();
+
+        assert_eq!(forms, vec![parse_str("(a b)")]);
+    }
+
+    #[test]
+    #[cfg(feature = "trace")]
+    fn trace() {
+        use std::sync::Mutex;
+
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                EVENTS.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        super::parse_str("(f 12 [x])").unwrap();
+
+        let events = EVENTS.lock().unwrap();
+        for event in ["lexing number starting at 1:4", "entering parse_call", "entering parse_sequence until `]`", "matched close bracket `]`"] {
+            assert!(events.iter().any(|e| e == event), "missing trace `{}` in {:?}", event, events);
+        }
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn form_size() {
+        const _: () = assert!(std::mem::size_of::<Form>() == 32);
+    }
+
+    #[test]
+    fn parse_source() {
+        assert_eq!(super::parse_str("(a) [b] 1").unwrap(), vec![parse_str("(a)"), parse_str("[b]"), Form::Integer(1)]);
+        assert_eq!(super::parse_str("").unwrap(), vec![]);
+
+        let err = super::parse_str("(a\n  b]").unwrap_err();
+        assert_eq!(err.render("(a\n  b]"), "error: Unexpected token: `]`, expected `)`\n --> 2:4\n  |\n2 |   b]\n  |    ^");
+
+        let err = super::parse_str("(a :)").unwrap_err();
+        assert_eq!(err.to_string(), "Empty keyword at 1:4");
+    }
+
+    #[test]
+    #[cfg(feature = "colored")]
+    fn render_colored() {
+        let err = super::parse_str("(a\n  b]").unwrap_err();
+        let rendered = err.render_colored("(a\n  b]");
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: Unexpected token: `]`, expected `)`\x1b[0m\n"));
+        assert!(rendered.ends_with("|    \x1b[31m^\x1b[0m"));
+        assert_eq!(super::parse_str("(a").unwrap_err().render_colored("(a"), "\x1b[1;31merror\x1b[0m\x1b[1m: Unexpected end of input, expected `)`\x1b[0m");
+    }
+
+    #[test]
+    fn complete_source() {
+        assert!(is_complete(""));
+        assert!(is_complete("(a [b] {:c 1})"));
+        assert!(is_complete("1 2"));
+        assert!(!is_complete("(a [b]"));
+        assert!(!is_complete("(a \"b)"));
+        assert!(!is_complete("'"));
+        assert!(!is_complete("#_ ; comment"));
+        assert!(is_complete("(a)) ; errors when parsed"));
+        assert!(is_complete("(a \n:\n"));
+        assert!(is_complete("(a :"));
+        assert!(is_complete("a.\n"));
+        assert!(!is_complete("(a \"b\\"));
+        assert!(!is_complete("(f |a b"));
+    }
+
+    #[test]
+    fn set() {
+        assert_eq!(parse_str("#{1 :a}"), Form::Set(vec![Form::Integer(1), Form::Keyword(Keyword::new("a").unwrap())]));
+        assert_eq!(parse_str("#{}"), Form::Set(vec![]));
+        assert_eq!(parse_str("#{1 2}").len(), Some(2));
+        assert!(super::parse_str("#{1 2]").is_err());
+    }
+
+    #[test]
+    fn tagged() {
+        let form = parse_str("#inst \"2024-01-01\"");
+        let Form::Tagged { tag, value } = &form else { panic!("expected a tagged literal") };
+
+        assert_eq!(tag.to_string(), "inst");
+        assert_eq!(**value, Form::String("2024-01-01".into()));
+        assert_eq!(form.to_string(), "#inst \"2024-01-01\"");
+        assert_eq!(parse_str("#point [1 #{2}]").children(), vec![&parse_str("[1 #{2}]")]);
+        assert_eq!(parse_str("#{#_1 2}"), Form::Set(vec![Form::Integer(2)]));
+        assert!(super::parse_str("[#inst]").is_err());
+        assert!(super::parse_str("#nil x").is_err());
+
+        for source in ["#inst \"x\"", "#my.ns/point [1 2]", "#a #tag c"] {
+            assert_eq!(parse_str(&parse_str(source).to_string()), parse_str(source), "parsing `{}`", source);
+        }
+    }
+
+    #[test]
+    fn odd_map() {
+        let err = super::parse_str("{a b\n c}").unwrap_err();
+
+        assert_eq!(err.position, Some(Position { line: 2, column: 3 }));
+        assert!(err.message.contains("even number of forms"));
+        assert!(err.message.contains("Add a value"));
+        assert!(err.message.contains("set literal `#{...}`"));
+        assert!(super::parse_str("{a}").unwrap_err().message.contains("`#{...}`"));
+        assert_eq!(super::parse_str("{a b").unwrap_err().message, "Unexpected end of input, expected `}`");
+    }
+
+    #[test]
+    fn suggestions() {
+        let err = super::parse_str("(f (g 1)").unwrap_err();
+
+        assert_eq!(err.hint.as_deref(), Some("add a `)` to close the form"));
+        assert_eq!(err.suggestions(), vec![Fix::Insert { position: None, token: Token::Close(')') }]);
+
+        let err = super::parse_str("{:a 1 :b}").unwrap_err();
+        let position = Some(Position { line: 1, column: 9 });
+
+        assert_eq!(err.hint.as_deref(), Some("add a value after the last key"));
+        assert_eq!(err.suggestions(), vec![Fix::Insert { position, token: Token::Nil }]);
+
+        let err = super::parse_str("(a b]").unwrap_err();
+        let position = Some(Position { line: 1, column: 5 });
+
+        assert_eq!(err.suggestions(), vec![
+            Fix::Delete { position, token: Token::Close(']') },
+            Fix::Insert { position, token: Token::Close(')') },
+        ]);
+        assert!(super::parse_str("\"a").unwrap_err().suggestions().is_empty());
+    }
+
+    #[test]
+    fn strict_brackets() {
+        let tokens = lex("(a b]").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+
+        assert_eq!(err.message, "Unexpected token: `]`, expected `)`");
+
+        let tokens = lex("[a b").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+
+        assert_eq!(err.message, "Unexpected end of input, expected `]`");
+    }
+
+    #[test]
+    fn stray_closing_bracket() {
+        for close in [')', ']', '}'] {
+            let source = format!("  {}", close);
+            let err = Parser::with_spans(lex_spanned(&source).unwrap(), ParserConfig::default())
+                .parse()
+                .unwrap_err();
+
+            assert_eq!(err.position, Some(Position { line: 1, column: 3 }));
+            assert_eq!(err.to_string(), format!("Unexpected closing bracket `{}` with no matching opener at 1:3", close));
+        }
+
+        let err = Parser::new(lex(")").unwrap()).parse().unwrap_err();
+        assert_eq!(err.position, None);
+        assert_eq!(err.to_string(), "Unexpected closing bracket `)` with no matching opener");
+
+        let err = Parser::with_spans(lex_spanned("(a\n  b]").unwrap(), ParserConfig::default())
+            .parse()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected token: `]`, expected `)` at 2:4");
+    }
+
+    #[test]
+    fn lenient_brackets() {
+        let config = ParserConfig { brackets: BracketMode::Lenient, ..Default::default() };
+        let tokens = lex("(a [b})").unwrap();
+        let form = Parser::with_config(tokens, config).parse().unwrap();
+
+        assert_eq!(form, parse_str("(a [b])"));
+    }
+
+    #[test]
+    fn open_bracket() {
+        let config = ParserConfig { brackets: BracketMode::Lenient, ..Default::default() };
+        let forms = Parser::with_config(lex("(a b] [c) {1 2) #{3] 4").unwrap(), config).parse_all().unwrap();
+        let brackets: Vec<_> = forms.iter().map(Form::open_bracket).collect();
+
+        assert_eq!(brackets, vec![Some("("), Some("["), Some("{"), Some("#{"), None]);
+        assert_eq!(parse_str("'[1]").open_bracket(), None);
+    }
+
+    #[test]
+    fn empty_call() {
+        let err = Parser::with_spans(lex_spanned("[1 ()]").unwrap(), ParserConfig::default())
+            .parse()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "An empty call `()` has nothing to call at 1:4");
+
+        let config = ParserConfig { allow_empty_call: true, ..Default::default() };
+        let form = Parser::with_config(lex("( )").unwrap(), config).parse().unwrap();
+        assert_eq!(form, Form::Call(vec![]));
+    }
+
+    #[test]
+    fn parse_recovering() {
+        let source = "(a]\n1\n[b {c}]\n) 2 )\n)";
+        let recovered = Parser::with_spans(lex_spanned(source).unwrap(), ParserConfig::default()).parse_recovering(10);
+
+        assert_eq!(recovered.forms, vec![Form::Integer(1), Form::Integer(2)]);
+        assert_eq!(
+            recovered.errors.iter().map(|err| err.position.unwrap()).collect::<Vec<_>>(),
+            vec![Position { line: 1, column: 3 }, Position { line: 3, column: 6 }, Position { line: 4, column: 1 }, Position { line: 4, column: 5 }, Position { line: 5, column: 1 }],
+        );
+        assert!(!recovered.truncated);
+
+        let recovered = Parser::new(lex(") ) ) ) )").unwrap()).parse_recovering(3);
+        assert_eq!(recovered.errors.len(), 3);
+        assert!(recovered.truncated);
+
+        let recovered = Parser::new(lex(") ) 1").unwrap()).parse_recovering(2);
+        assert_eq!(recovered, RecoveredForms { forms: vec![], errors: recovered.errors.clone(), truncated: true });
+
+        // Limits fail without consuming the form, which is then skipped
+        let config = ParserConfig { max_nodes: Some(2), ..Default::default() };
+        let recovered = Parser::with_config(lex("1 2 3 (4 5) 6").unwrap(), config).parse_recovering(usize::MAX);
+        assert_eq!(recovered.forms, vec![Form::Integer(1), Form::Integer(2)]);
+        assert_eq!(recovered.errors.len(), 3);
+
+        let config = ParserConfig { max_depth: 0, ..Default::default() };
+        let recovered = Parser::with_config(lex("(a b) c").unwrap(), config).parse_recovering(usize::MAX);
+        assert_eq!((recovered.forms.len(), recovered.errors.len()), (0, 2));
+    }
+
+    #[test]
+    fn skip_balanced() {
+        let mut parser = Parser::new(lex("(f (g [1 2) #{3}) x) y").unwrap());
+
+        parser.next_token();
+        parser.next_token();
+        assert!(parser.skip_balanced());
+        assert_eq!(parser.tokens.peek(), Some(&Token::Symbol(Box::new(Symbol { head: "x".to_string(), tail: vec![], slash: false }))));
+        assert_eq!(parser.depth, 1);
+
+        assert!(parser.skip_balanced());
+        assert!(parser.skip_balanced());
+        assert_eq!(parser.depth, 0);
+        assert_eq!(parser.parse(), Ok(parse_str("y")));
+        assert!(!parser.skip_balanced());
+
+        let mut parser = Parser::new(lex("(a (b").unwrap());
+        assert!(parser.skip_balanced());
+        assert_eq!(parser.tokens.peek(), None);
+    }
+
+    #[test]
+    fn intern_strings() {
+        let source = "[\"foo\" \"bar\" (f \"foo\")]";
+        let strings = |form: &Form| crate::ast::find_all(form, |form| matches!(form, Form::String(_)))
+            .into_iter()
+            .map(|form| match form {
+                Form::String(s) => s.clone(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        let form = Parser::new(lex(source).unwrap()).parse().unwrap();
+        let plain = strings(&form);
+        assert_eq!(plain[0], plain[2]);
+        assert!(!Arc::ptr_eq(&plain[0], &plain[2]));
+
+        let config = ParserConfig { intern_strings: true, ..Default::default() };
+        let interned_form = Parser::with_config(lex(source).unwrap(), config).parse().unwrap();
+        let interned = strings(&interned_form);
+        assert!(Arc::ptr_eq(&interned[0], &interned[2]));
+        assert!(!Arc::ptr_eq(&interned[0], &interned[1]));
+        assert_eq!(interned_form, form);
+    }
+
+    #[test]
+    fn reader_conditional() {
+        let parse_with = |source: &str, feature: &str| {
+            let config = ParserConfig { features: HashSet::from([Keyword::new(feature).unwrap()]), ..Default::default() };
+
+            Parser::with_spans(lex_spanned(source).unwrap(), config).parse_all()
+        };
+        let source = "[1 #?(:clj \"jvm\" :cljs \"js\") 2] #?(:clj x)";
+
+        assert_eq!(parse_with(source, "clj"), Ok(vec![parse_str("[1 \"jvm\" 2]"), parse_str("x")]));
+        assert_eq!(parse_with(source, "cljs"), Ok(vec![parse_str("[1 \"js\" 2]")]));
+        assert_eq!(parse_with(source, "py"), Ok(vec![parse_str("[1 2]")]));
+        assert_eq!(parse_with("{:a #?(:clj 1 :clj 2)}", "clj"), Ok(vec![parse_str("{:a 1}")]));
+        assert!(is_complete("#?(:clj x)"));
+        assert!(!is_complete("#?"));
+
+        let err = parse_with("#?(:clj)", "clj").unwrap_err();
+        assert_eq!(err.to_string(), "The reader conditional feature `:clj` has no form at 1:8");
+
+        let err = parse_with("#?(clj x)", "clj").unwrap_err();
+        assert_eq!(err.to_string(), "A reader conditional feature must be a keyword, found a symbol at 1:4");
+
+        let err = parse_with("#?[:clj x]", "clj").unwrap_err();
+        assert_eq!(err.to_string(), "Expected `(` after the reader conditional `#?` at 1:3");
+    }
+
+    #[test]
+    fn presized_collections() {
+        let source = format!("[{} (f 'x #_y z) {{:a 1 :b [2 3]}} #{{}}]", "1 ".repeat(1000));
+        let form = Parser::new(lex(&source).unwrap()).parse().unwrap();
+
+        assert_eq!(form, parse(lex(&source).unwrap().into_iter().peekable()).unwrap().0);
+
+        let Form::List(forms) = form else { panic!("expected a list") };
+        assert_eq!(forms.len(), 1003);
+        assert_eq!(forms.capacity(), 1003);
+        assert!(matches!(&forms[1000], Form::Call(call) if call.capacity() == 4));
+        assert!(matches!(&forms[1001], Form::Map(map) if map.capacity() == 2));
+    }
+
+    #[test]
+    fn max_depth() {
+        let nested_map = |depth: usize| format!("{}1{}", "{:a ".repeat(depth), "}".repeat(depth));
+        let parse_with = |source: &str, max_depth: usize| {
+            let config = ParserConfig { max_depth, ..Default::default() };
+
+            Parser::with_spans(lex_spanned(source).unwrap(), config).parse_all()
+        };
+
+        assert!(parse_with(&nested_map(9), 10).is_ok());
+        assert_eq!(
+            parse_with(&nested_map(10), 10).unwrap_err().to_string(),
+            "Forms are nested deeper than the limit of 10 levels at 1:38",
+        );
+        assert!(parse_with(&format!("{{{} 1}}", nested_map(9)), 10).is_err());
+        assert!(parse_with(&format!("{}x", "'".repeat(10)), 10).is_err());
+
+        let err = parse_with(&nested_map(100_000), 256).unwrap_err();
+        assert_eq!(err.message, "Forms are nested deeper than the limit of 256 levels");
+        assert!(Parser::new(lex(&nested_map(100)).unwrap()).parse().is_ok());
+    }
+
+    #[test]
+    fn concat_strings() {
+        let parse_with = |source: &str, concat_strings: bool| {
+            let config = ParserConfig { concat_strings, ..Default::default() };
+
+            Parser::with_config(lex_lossless(source).unwrap().into_iter().map(|(token, _)| token).collect(), config)
+                .parse_all()
+                .unwrap()
+        };
+
+        assert_eq!(parse_with("\"foo\" \"bar\"", true), vec![Form::String("foobar".into())]);
+        assert_eq!(parse_with("\"foo\" \"bar\"", false), vec![Form::String("foo".into()), Form::String("bar".into())]);
+        assert_eq!(parse_with("(f \"a\" ; one\n \"b\"\n\"c\" 1 \"d\")", true), vec![parse_str("(f \"abc\" 1 \"d\")")]);
+        assert_eq!(parse_with("[\"a\" #_x \"c\"]", true), vec![parse_str("[\"a\" \"c\"]")]);
+        assert_eq!(parse_with("[\"a\" #_\"b\" \"c\"]", true), vec![parse_str("[\"a\"]")]);
+    }
+
+    #[test]
+    fn max_nodes() {
+        let parse_with = |source: &str, max_nodes: usize| {
+            let config = ParserConfig { max_nodes: Some(max_nodes), ..Default::default() };
+
+            Parser::with_spans(lex_spanned(source).unwrap(), config).parse_all()
+        };
+        // The list itself is a form too
+        let list = format!("[{}]", "1 ".repeat(999));
+
+        assert!(parse_with(&list, 1000).is_ok());
+
+        let err = parse_with(&list, 999).unwrap_err();
+        assert_eq!(err.message, "The input has more forms than the limit of 999");
+        assert_eq!(err.position, Some(Position { line: 1, column: 1998 }));
+        assert!(parse_with("(a [b]) c", 5).is_ok());
+        assert!(parse_with("(a [b]) c", 4).is_err());
+    }
+
+    #[test]
+    fn error_token() {
+        let config = LexerConfig { error_tokens: true, ..Default::default() };
+        let tokens = Lexer::with_config("[1 ~ 2]", config).collect_spanned().unwrap();
+        let recovered = Parser::with_spans(tokens, ParserConfig::default()).parse_recovering(10);
+
+        assert_eq!(recovered.errors.len(), 1);
+        assert_eq!(recovered.errors[0].to_string(), "Unexpected character: ~ at 1:4");
+    }
+
+    #[test]
+    fn parse_program() {
+        let source = "(def x 1)\n(print x)";
+
+        assert_eq!(parse_program_as_do(lex(source).unwrap()), Ok(parse_str("(do (def x 1) (print x))")));
+        assert_eq!(parse_program_as_do(vec![]), Ok(parse_str("(do)")));
+
+        let head = Symbol { head: "progn".to_string(), tail: vec![], slash: false };
+        assert_eq!(Parser::new(lex(source).unwrap()).parse_program(head), Ok(parse_str("(progn (def x 1) (print x))")));
+    }
+
+    #[test]
+    fn dot_as_method_call() {
+        let symbol = |name: &str| Form::Symbol(Box::new(Symbol { head: name.to_string(), tail: vec![], slash: false }));
+        let dot = |object, member| Form::call(symbol("."), [object, member]);
+        let parse_with = |source: &str, dot_as_method_call| {
+            let config = ParserConfig { dot_as_method_call, ..Default::default() };
+
+            Parser::with_config(lex(source).unwrap(), config).parse().unwrap()
+        };
+
+        assert_eq!(parse_with("obj.method", true), dot(symbol("obj"), symbol("method")));
+        assert_eq!(parse_with("a.b.c", true), dot(dot(symbol("a"), symbol("b")), symbol("c")));
+        assert_eq!(parse_with("(f obj)", true), parse_str("(f obj)"));
+        assert_eq!(parse_with("a.b/c", true), parse_str("a.b/c"));
+
+        assert_eq!(parse_with("obj.method", false), parse_str("obj.method"));
+        assert_eq!(parse_with("a.b.c", false), Form::Symbol(Box::new(Symbol {
+            head: "a".to_string(),
+            tail: vec!["b".to_string(), "c".to_string()],
+            slash: false,
+        })));
+    }
+
+    #[test]
+    fn compound_keys() {
+        let source = "{:a 1\n [b] 2 #{c} 3}";
+
+        assert!(Parser::with_spans(lex_spanned(source).unwrap(), ParserConfig::default()).parse().is_ok());
+
+        let config = ParserConfig { allow_compound_keys: false, ..Default::default() };
+        let err = Parser::with_spans(lex_spanned(source).unwrap(), config).parse().unwrap_err();
+        assert_eq!(err.to_string(), "A map key can't be a set at 2:8");
+    }
+
+    #[test]
+    fn try_from_form() {
+        assert_eq!(i64::try_from(parse_str("42")), Ok(42));
+        assert_eq!(f64::try_from(parse_str("1.5")), Ok(1.5));
+        assert_eq!(f64::try_from(&parse_str("2")), Ok(2.0));
+        assert_eq!(String::try_from(parse_str("\"foo\"")), Ok("foo".to_string()));
+        assert_eq!(char::try_from(&parse_str("\\a")), Ok('a'));
+        assert_eq!(bool::try_from(parse_str("true")), Ok(true));
+        assert_eq!(bool::try_from(&parse_str("false")), Ok(false));
+    }
+
+    #[test]
+    fn try_from_form_mismatch() {
+        assert_eq!(i64::try_from(parse_str("\"42\"")), Err("Expected an integer, found a string".to_string()));
+        assert_eq!(bool::try_from(&parse_str("nil")), Err("Expected a bool, found nil".to_string()));
+        assert_eq!(String::try_from(parse_str("[1]")), Err("Expected a string, found a list".to_string()));
+    }
+
+    #[test]
+    fn try_from_token() {
+        for source in ["42", "1.5", "\"s\"", "\\c", "a.b", ":k", "true", "nil"] {
+            let token = lex(source).unwrap().remove(0);
+
+            assert_eq!(Form::try_from(token), Ok(parse_str(source)), "converting `{}`", source);
+        }
+
+        assert_eq!(Form::try_from(Token::Open('(')), Err("Unexpected token: `(`, expected an atom".to_string()));
+        assert_eq!(Form::try_from(Token::Close(']')), Err("Unexpected token: `]`, expected an atom".to_string()));
+        assert!(Form::try_from(Token::Quote).is_err());
+    }
+
+    #[test]
+    fn discard() {
+        assert_eq!(parse_str("[1 #_2 3]"), parse_str("[1 3]"));
+        assert_eq!(parse_str("[1 #_ (expensive-call) 3 #_4]"), parse_str("[1 3]"));
+        assert_eq!(parse_str("[#_ #_ 1 2 3]"), parse_str("[3]"));
+        assert_eq!(parse_str("{:a #_ :b 1}"), parse_str("{:a 1}"));
+        assert_eq!(parse_str("#_ a b"), parse_str("b"));
+        assert_eq!(parse_str("(a #; b c)"), parse_str("(a c)"));
+        assert_eq!(parse_str("[#;#_ 1 2 #; ; comment\n 3 4]"), parse_str("[4]"));
+    }
+
+    #[test]
+    fn classify() {
+        let cases = vec![
+            ("(f)", FormKind::Call, false, true),
+            ("f", FormKind::Symbol, true, false),
+            ("1.5", FormKind::Float, true, false),
+            ("1", FormKind::Integer, true, false),
+            ("\"s\"", FormKind::String, true, false),
+            ("\\c", FormKind::Char, true, false),
+            (":k", FormKind::Keyword, true, false),
+            ("false", FormKind::Bool, true, false),
+            ("nil", FormKind::Nil, true, false),
+            ("[1]", FormKind::List, false, true),
+            ("{1 2}", FormKind::Map, false, true),
+            ("#{1}", FormKind::Set, false, true),
+            ("'a", FormKind::Quote, false, false),
+            ("#inst \"2024\"", FormKind::Tagged, false, false),
+        ];
+
+        for (source, kind, atom, collection) in cases {
+            let form = parse_str(source);
+            assert_eq!(form.kind(), kind, "kind of `{}`", source);
+            assert_eq!(form.is_atom(), atom, "`{}` is an atom", source);
+            assert_eq!(form.is_collection(), collection, "`{}` is a collection", source);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let source = "(defn f [a b] {:x 1.0 :y \"s\\n\"} #{\\a nil} 'q.r)";
+        let form = parse_str(source);
+
+        assert_eq!(form.to_string(), source);
+        assert_eq!(parse_str(&form.to_string()), form);
+        assert_eq!(format!("{:>10}", parse_str("(+ 1 2)")), "   (+ 1 2)");
+        assert_eq!(format!("{:<10}|", parse_str("[1]")), "[1]       |");
+        assert_eq!(format!("{:^7}", parse_str(":k")), "  :k   ");
+    }
+
+    #[test]
+    fn len_and_get() {
+        let list = parse_str("[1 2 3]");
+        let call = parse_str("(foo 1)");
+
+        assert_eq!(list.len(), Some(3));
+        assert_eq!(list.get(0), Some(&Form::Integer(1)));
+        assert_eq!(list.get(2), Some(&Form::Integer(3)));
+        assert_eq!(list.get(3), None);
+        assert_eq!(call.len(), Some(2));
+        assert_eq!(call.get(1), Some(&Form::Integer(1)));
+        assert_eq!(list.is_empty(), Some(false));
+        assert_eq!(parse_str("[]").is_empty(), Some(true));
+        assert_eq!(Form::Integer(1).len(), None);
+        assert_eq!(Form::Integer(1).get(0), None);
+    }
+
+    #[test]
+    fn children() {
+        assert_eq!(parse_str("(f 1)").children(), vec![&parse_str("f"), &Form::Integer(1)]);
+        assert_eq!(parse_str("{1 2 3 4}").children(), vec![&Form::Integer(1), &Form::Integer(2), &Form::Integer(3), &Form::Integer(4)]);
+        assert_eq!(parse_str("'a").children(), vec![&parse_str("a")]);
+        assert!(Form::Integer(1).children().is_empty());
+
+        let incremented = parse_str("{1 [2]}").map_forms(|form| match form {
+            Form::Integer(i) => Form::Integer(i + 1),
+            form => form,
+        });
+        assert_eq!(incremented, parse_str("{2 [2]}"));
+    }
+
+    #[test]
+    fn into_children() {
+        assert_eq!(parse_str("[1 2]").into_children(), Some(vec![Form::Integer(1), Form::Integer(2)]));
+        assert_eq!(parse_str("(f)").into_children(), Some(vec![parse_str("f")]));
+        assert_eq!(parse_str("{1 2}").into_children(), None);
+        assert_eq!(parse_str("{1 2}").into_map_entries(), Some(vec![(Form::Integer(1), Form::Integer(2))]));
+        assert_eq!(parse_str("[1 2]").into_map_entries(), None);
+        assert_eq!(Form::Integer(1).into_children(), None);
+        assert_eq!(Form::Integer(1).into_map_entries(), None);
+    }
+
+    #[test]
+    fn into_call_parts() {
+        let (head, body) = parse_str("(do a b c)").into_call_parts().unwrap();
+        assert_eq!(head, parse_str("do"));
+        assert_eq!(Form::List(body), parse_str("[a b c]"));
+
+        assert_eq!(parse_str("(f)").into_call_parts(), Some((parse_str("f"), vec![])));
+        assert_eq!(parse_str("[do a]").into_call_parts(), None);
+        assert_eq!(Form::Call(vec![]).into_call_parts(), None);
+    }
+
+    #[test]
+    fn key_kind() {
+        assert_eq!(parse_str("{:a 1 :b \"x\"}").key_kind(), Some(FormKind::Keyword));
+        assert_eq!(parse_str("{\"a\" 1 \"b\" :x}").key_kind(), Some(FormKind::String));
+        assert_eq!(parse_str("{:a 1 \"b\" 2}").key_kind(), None);
+        assert_eq!(parse_str("{}").key_kind(), None);
+        assert_eq!(parse_str("[:a 1]").key_kind(), None);
+    }
+
+    #[test]
+    fn callee_symbol() {
+        let call = parse_str("(foo x)");
+        let symbol = call.callee_symbol().unwrap();
+        assert_eq!(symbol.head, "foo");
+        assert!(symbol.tail.is_empty());
+
+        let call = parse_str("(math.sqrt x)");
+        let symbol = call.callee_symbol().unwrap();
+        assert_eq!(symbol.head, "math");
+        assert_eq!(symbol.tail, vec!["sqrt".to_string()]);
+
+        assert_eq!(parse_str("((get-fn) x)").callee_symbol(), None);
+        assert_eq!(Form::Call(vec![]).callee_symbol(), None);
+        assert_eq!(parse_str("[foo x]").callee_symbol(), None);
+    }
+
+    #[test]
+    fn map_entries() {
+        let map = parse_str("{:a 1 :b 2}");
+        let entries = map.map_entries().unwrap().collect::<Vec<_>>();
+
+        assert_eq!(entries, vec![
+            (&Form::Keyword(Keyword::new("a").unwrap()), &Form::Integer(1)),
+            (&Form::Keyword(Keyword::new("b").unwrap()), &Form::Integer(2)),
+        ]);
+        assert_eq!(map.map_get(&Form::Keyword(Keyword::new("b").unwrap())), Some(&Form::Integer(2)));
+        assert_eq!(map.map_get(&Form::Keyword(Keyword::new("c").unwrap())), None);
+        assert!(Form::Integer(1).map_entries().is_none());
+        assert_eq!(Form::Integer(1).map_get(&Form::Integer(1)), None);
+    }
+
+    #[test]
+    fn assoc_dissoc_merge() {
+        let map = parse_str("{:a 1 :b 2}");
+
+        assert_eq!(map.clone().assoc(parse_str(":a"), parse_str("3")), parse_str("{:a 3 :b 2}"));
+        assert_eq!(map.clone().assoc(parse_str(":c"), parse_str("3")), parse_str("{:a 1 :b 2 :c 3}"));
+        assert_eq!(map.clone().dissoc(&parse_str(":a")), parse_str("{:b 2}"));
+        assert_eq!(map.clone().dissoc(&parse_str(":c")), map);
+        assert_eq!(map.clone().merge(parse_str("{:b 3 :c 4}")), parse_str("{:a 1 :b 3 :c 4}"));
+
+        assert_eq!(parse_str("[1]").assoc(parse_str(":a"), parse_str("1")), parse_str("[1]"));
+        assert_eq!(parse_str("[1]").dissoc(&parse_str("1")), parse_str("[1]"));
+        assert_eq!(map.clone().merge(parse_str("[:c 4]")), map);
+    }
+
+    #[test]
+    fn get_path() {
+        let mut form = parse_str("(defn foo [a b] {:body (+ a b)})");
+        let path = FormPath(vec![
+            PathSegment::Index(3),
+            PathSegment::MapKey(Form::Keyword(Keyword::new("body").unwrap())),
+            PathSegment::Index(2),
+        ]);
+
+        assert_eq!(form.get_path(&path), Some(&parse_str("b")));
+        assert_eq!(form.get_path(&FormPath(vec![PathSegment::Index(2), PathSegment::Index(0)])), Some(&parse_str("a")));
+        assert_eq!(form.get_path(&FormPath::default()), Some(&form));
+        assert_eq!(form.get_path(&FormPath(vec![PathSegment::Index(4)])), None);
+        assert_eq!(form.get_path(&FormPath(vec![PathSegment::Index(2), PathSegment::Index(2)])), None);
+        assert_eq!(form.get_path(&FormPath(vec![PathSegment::MapKey(Form::Integer(0))])), None);
+
+        *form.get_path_mut(&path).unwrap() = Form::Integer(1);
+        assert_eq!(form, parse_str("(defn foo [a b] {:body (+ a 1)})"));
+        assert_eq!(form.get_path_mut(&FormPath(vec![PathSegment::Index(9)])), None);
+    }
+
+    #[test]
+    fn keyword_map() {
+        let map = parse_str("{:port 8080 :host \"localhost\"}");
+
+        assert_eq!(map.as_keyword_map(), Some(vec![
+            ("port", &Form::Integer(8080)),
+            ("host", &Form::String("localhost".into())),
+        ]));
+        assert_eq!(parse_str("{:port 8080 \"host\" 1}").as_keyword_map(), None);
+        assert_eq!(parse_str("[:a 1]").as_keyword_map(), None);
+        assert_eq!(parse_str("{}").as_keyword_map(), Some(vec![]));
+        assert_eq!(Form::keyword_map(vec![
+            (Keyword::new("port").unwrap(), Form::Integer(8080)),
+            (Keyword::new("host").unwrap(), Form::String("localhost".into())),
+        ]), map);
+    }
+
+    #[test]
+    fn char_value() {
+        let form = parse_str("\\a");
+
+        assert_eq!(form.char_value(), Some(97));
+        assert_eq!(Form::char_from_u32(97), Some(form));
+        assert_eq!(Form::char_from_u32(0x1F600), Some(Form::Char('😀')));
+        assert_eq!(Form::char_from_u32(0xD800), None);
+        assert_eq!(Form::char_from_u32(0x110000), None);
+        assert_eq!(Form::Integer(97).char_value(), None);
+    }
+
+    #[test]
+    fn validate_constructible() {
+        assert_eq!(Form::try_new_keyword("my-key"), Ok(parse_str(":my-key")));
+        assert_eq!(Form::try_new_keyword("my key"), Err("Unexpected character:   in the keyword `:my key`".to_string()));
+        assert_eq!(Form::try_new_keyword("ns/name"), Ok(parse_str(":ns/name")));
+        assert_eq!(Form::try_new_char('a'), Ok(Form::Char('a')));
+        assert_eq!(Form::try_new_char('\u{a0}'), Err("Writing a char as `\\\u{a0}` doesn't lex back as the same form".to_string()));
+
+        let source = "(f 'x |a b| [1 ##NaN -9223372036854775808] {:k \"a\\n\"} #{\\space nil} #inst \"2024\" a.b/c)";
+        assert_eq!(parse_str(source).validate_constructible(), Ok(()));
+
+        let camel = Form::Keyword(Keyword::new("my-key").unwrap().to_camel_case());
+        let nested = Form::list([Form::Integer(1), Form::call(parse_str("f"), [camel])]);
+        assert_eq!(nested.validate_constructible(), Err("Writing a keyword as `:myKey` doesn't lex back as the same form".to_string()));
+
+        let symbol = Form::Symbol(Box::new(Symbol { head: "a".to_string(), tail: vec!["b c".to_string()], slash: false }));
+        assert!(symbol.quote().validate_constructible().is_err());
+
+        for name in ["my-key", "ns/name", "a1", "1a", "-"] {
+            let keyword = Keyword::new(name).map(Form::Keyword).unwrap();
+            assert_eq!(Form::try_new_keyword(name).is_ok(), keyword.validate_constructible().is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn index_map() {
+        let source = "{:b 1 \"a\" 2 [1 2] 3 1.5 4 0.0 5 :b 6}";
+        let map = parse_str(source);
+        let indexed = map.clone().index_map().unwrap();
+        let keys = [":b", "\"a\"", "[1 2]", "1.5", "-0.0", ":c", "[1]"];
+
+        for key in keys.iter().map(|key| parse_str(key)) {
+            assert_eq!(indexed.get(&key), map.map_get(&key));
+            assert_eq!(indexed.contains_key(&key), map.map_get(&key).is_some());
+        }
+
+        assert_eq!(indexed.get(&parse_str(":b")), Some(&Form::Integer(1)));
+        assert_eq!(indexed.len(), 6);
+        assert_eq!(indexed.iter().collect::<Vec<_>>(), map.map_entries().unwrap().collect::<Vec<_>>());
+        assert!(parse_str("[1 2]").index_map().is_none());
+    }
+
+    #[test]
+    fn nested_quote() {
+        let form = parse_str("''[1 2]");
+        let list = Form::List(vec![Form::Integer(1), Form::Integer(2)]);
+
+        assert_eq!(form, list.clone().quote().quote());
+
+        let form = form.unquote_once();
+        assert!(form.is_quoted());
+
+        let form = form.unquote_once();
+        assert!(!form.is_quoted());
+        assert_eq!(form, list);
+    }
+
+    #[test]
+    fn eq_unqualified() {
+        assert!(parse_str("(math.sqrt x)").eq_unqualified(&parse_str("(sqrt x)")));
+        assert!(parse_str("[a.b.c {:k d.e}]").eq_unqualified(&parse_str("[c {:k e}]")));
+        assert!(Form::Keyword(Keyword::new("ns/foo").unwrap()).eq_unqualified(&Form::Keyword(Keyword::new("foo").unwrap())));
+        assert_ne!(parse_str("math.sqrt"), parse_str("sqrt"));
+
+        assert!(!parse_str("math.sqrt").eq_unqualified(&parse_str("math.pow")));
+        assert!(!parse_str("(sqrt x)").eq_unqualified(&parse_str("[sqrt x]")));
+        assert!(!parse_str("sqrt").eq_unqualified(&parse_str(":sqrt")));
+    }
+
+    #[test]
+    fn seq_eq() {
+        let (list, quoted) = (parse_str("[1 [2 3] {:k [4]}]"), parse_str("'(1 (2 3) {:k '(4)})"));
+
+        assert!(list.seq_eq(&quoted));
+        assert!(quoted.seq_eq(&list));
+        assert_ne!(list, quoted);
+        assert!(parse_str("[f x]").seq_eq(&parse_str("(f x)")));
+
+        assert!(!parse_str("[1 2]").seq_eq(&parse_str("'(1 3)")));
+        assert!(!parse_str("[1 2]").seq_eq(&parse_str("[1 2 3]")));
+        assert!(!parse_str("[1]").seq_eq(&parse_str("#{1}")));
+        assert!(!parse_str("[1]").seq_eq(&parse_str("''(1)")));
+    }
+}
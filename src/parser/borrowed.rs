@@ -0,0 +1,236 @@
+//! Parsing into [`FormRef`], a form whose text borrows from the source instead of owning it,
+//! for read-only analysis where allocating every symbol and string is wasteful.
+use crate::lexer::{token::{TextKind, Token}, Lexer, LexerConfig, Span};
+
+use super::{Build, FormKind, ParseError, Parser, ParserConfig};
+
+/// A form like [`Form`](super::Form) whose symbols, strings and keywords are slices of the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormRef<'a> {
+    Call(Vec<FormRef<'a>>),
+    /// The symbol as written, like `math.sqrt`, without the bars of a verbatim symbol.
+    Symbol(&'a str),
+    Float(f64),
+    Integer(i64),
+    /// A complex number like `3+4i`.
+    #[cfg(feature = "complex")]
+    Complex { re: f64, im: f64 },
+    /// The text between the quotes, with its escapes as written.
+    String(&'a str),
+    Char(char),
+    /// The keyword without its `:`.
+    Keyword(&'a str),
+    Bool(bool),
+    Nil,
+    List(Vec<FormRef<'a>>),
+    Map(Vec<(FormRef<'a>, FormRef<'a>)>),
+    Set(Vec<FormRef<'a>>),
+    Quote(Box<FormRef<'a>>),
+    /// A tagged literal, with the tag symbol as written after `#`.
+    Tagged { tag: &'a str, value: Box<FormRef<'a>> },
+}
+
+/// Lexes and parses every form in the source, like [`parse_str`](super::parse_str) with the default
+/// configuration, but borrowing the text of atoms from the source.
+pub fn parse_borrowed(source: &str) -> Result<Vec<FormRef<'_>>, ParseError> {
+    parse_borrowed_with_config(source, ParserConfig::default())
+}
+
+/// Lexes and parses every form in the source like [`parse_borrowed`], using the given configuration.
+/// Joining strings with [`ParserConfig::concat_strings`] is an error, since a joined string isn't in the source.
+pub fn parse_borrowed_with_config(source: &str, config: ParserConfig) -> Result<Vec<FormRef<'_>>, ParseError> {
+    let tokens = Lexer::with_config(source, LexerConfig { borrowed: true, ..Default::default() }).collect_spanned()?;
+
+    Parser::with_builder(tokens, config, FormRefBuilder { source }).parse_all()
+}
+
+/// Builds [`FormRef`]s from tokens lexed with [`LexerConfig::borrowed`] set, slicing their text from the source.
+struct FormRefBuilder<'a> {
+    source: &'a str,
+}
+
+impl<'a> FormRefBuilder<'a> {
+    /// Returns the text of the token at `span`.
+    fn text(&self, span: Option<&Span>) -> &'a str {
+        &self.source[span.map_or(0..0, |span| span.range.clone())]
+    }
+}
+
+impl<'a> Build for FormRefBuilder<'a> {
+    type Form = FormRef<'a>;
+
+    fn atom(&mut self, token: Token, span: Option<&Span>, config: &ParserConfig) -> Result<FormRef<'a>, String> {
+        let text = self.text(span);
+
+        match token {
+            Token::Text(TextKind::Symbol) => match text.strip_prefix('|').and_then(|s| s.strip_suffix('|')) {
+                Some(verbatim) => Ok(FormRef::Symbol(verbatim)),
+                None if config.dot_as_method_call && is_dotted(text) => Ok(method_call(text)),
+                None => Ok(FormRef::Symbol(text)),
+            },
+            Token::Text(TextKind::String) => Ok(FormRef::String(text.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(text))),
+            Token::Text(TextKind::Keyword) => Ok(FormRef::Keyword(text.strip_prefix(':').unwrap_or(text))),
+            Token::Integer(i) => Ok(FormRef::Integer(i)),
+            Token::Float(f) => Ok(FormRef::Float(f)),
+            #[cfg(feature = "complex")]
+            Token::Complex { re, im } => Ok(FormRef::Complex { re, im }),
+            Token::Char(c) => Ok(FormRef::Char(c)),
+            Token::Bool(b) => Ok(FormRef::Bool(b)),
+            Token::Nil => Ok(FormRef::Nil),
+            token => Err(format!("Unexpected token: `{}`, expected an atom", token)),
+        }
+    }
+
+    fn tagged(&mut self, tag: Token, span: Option<&Span>, value: FormRef<'a>) -> Result<FormRef<'a>, String> {
+        match tag {
+            Token::Text(TextKind::Tag) => Ok(FormRef::Tagged { tag: &self.text(span)[1..], value: Box::new(value) }),
+            tag => Err(format!("Unexpected token: `{}`, expected a tag", tag)),
+        }
+    }
+
+    fn call(&mut self, forms: Vec<FormRef<'a>>) -> FormRef<'a> {
+        FormRef::Call(forms)
+    }
+
+    fn list(&mut self, forms: Vec<FormRef<'a>>) -> FormRef<'a> {
+        FormRef::List(forms)
+    }
+
+    fn map(&mut self, entries: Vec<(FormRef<'a>, FormRef<'a>)>) -> FormRef<'a> {
+        FormRef::Map(entries)
+    }
+
+    fn set(&mut self, forms: Vec<FormRef<'a>>) -> FormRef<'a> {
+        FormRef::Set(forms)
+    }
+
+    fn quote(&mut self, form: FormRef<'a>) -> FormRef<'a> {
+        FormRef::Quote(Box::new(form))
+    }
+
+    fn kind(form: &FormRef<'a>) -> FormKind {
+        match form {
+            FormRef::Call(_) => FormKind::Call,
+            FormRef::Symbol(_) => FormKind::Symbol,
+            FormRef::Float(_) => FormKind::Float,
+            FormRef::Integer(_) => FormKind::Integer,
+            #[cfg(feature = "complex")]
+            FormRef::Complex { .. } => FormKind::Complex,
+            FormRef::String(_) => FormKind::String,
+            FormRef::Char(_) => FormKind::Char,
+            FormRef::Keyword(_) => FormKind::Keyword,
+            FormRef::Bool(_) => FormKind::Bool,
+            FormRef::Nil => FormKind::Nil,
+            FormRef::List(_) => FormKind::List,
+            FormRef::Map(_) => FormKind::Map,
+            FormRef::Set(_) => FormKind::Set,
+            FormRef::Quote(_) => FormKind::Quote,
+            FormRef::Tagged { .. } => FormKind::Tagged,
+        }
+    }
+
+    fn keyword<'f>(form: &'f FormRef<'a>) -> Option<&'f str> {
+        match form {
+            FormRef::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether a lexed symbol has parts split by `.`, without a `/` splitting its namespace from its name.
+fn is_dotted(text: &str) -> bool {
+    text.contains('.') && text.split('.').find(|part| part.contains('/')).is_none_or(|part| part.starts_with('/'))
+}
+
+/// Expands a dotted symbol into nested `(. object member)` calls, like the parser does for [`Form`](super::Form).
+fn method_call(text: &str) -> FormRef<'_> {
+    let mut parts = text.split('.');
+    let head = FormRef::Symbol(parts.next().unwrap_or_default());
+
+    parts.fold(head, |object, member| FormRef::Call(vec![FormRef::Symbol("."), object, FormRef::Symbol(member)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{lexer::{keyword::Keyword, lex_spanned, Position}, parser::{parse_str, Form, Parser, ParserConfig}};
+
+    use super::{parse_borrowed, parse_borrowed_with_config, FormRef};
+
+    /// Checks that a borrowed form has the same structure and text as an owned one.
+    fn same(borrowed: &FormRef, owned: &Form) -> bool {
+        let all_same = |a: &[FormRef], b: &[Form]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b));
+
+        match (borrowed, owned) {
+            (FormRef::Call(a), Form::Call(b)) | (FormRef::List(a), Form::List(b)) | (FormRef::Set(a), Form::Set(b)) => all_same(a, b),
+            (FormRef::Map(a), Form::Map(b)) => a.len() == b.len()
+                && a.iter().zip(b).all(|((ka, va), (kb, vb))| same(ka, kb) && same(va, vb)),
+            (FormRef::Quote(a), Form::Quote(b)) => same(a, b),
+            (FormRef::Tagged { tag: ta, value: a }, Form::Tagged { tag: tb, value: b }) => *ta == tb.to_string() && same(a, b),
+            (FormRef::Symbol(a), Form::Symbol(b)) => *a == b.to_string() || (b.tail.is_empty() && *a == b.head),
+            (FormRef::String(a), Form::String(b)) => *a == &**b,
+            (FormRef::Keyword(a), Form::Keyword(b)) => *a == b.as_str(),
+            (FormRef::Integer(a), Form::Integer(b)) => a == b,
+            (FormRef::Float(a), Form::Float(b)) => a == b,
+            (FormRef::Char(a), Form::Char(b)) => a == b,
+            (FormRef::Bool(a), Form::Bool(b)) => a == b,
+            (FormRef::Nil, Form::Nil) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn borrows_from_source() {
+        let source = "(defn greet [name] (str \"hello, \" name :loud)) #_(skip) 'math.pi #{1.5 \\a} {:k nil} #inst \"2024\"";
+        let borrowed = parse_borrowed(source).unwrap();
+        let owned = parse_str(source).unwrap();
+
+        assert_eq!(borrowed.len(), owned.len());
+        assert!(borrowed.iter().zip(&owned).all(|(a, b)| same(a, b)));
+
+        let FormRef::Call(forms) = &borrowed[0] else { panic!("expected a call") };
+        let FormRef::Symbol(head) = forms[0] else { panic!("expected a symbol") };
+
+        assert_eq!(head, "defn");
+        assert!(source.as_bytes().as_ptr_range().contains(&head.as_ptr()));
+        assert_eq!(head.as_ptr(), source[1..].as_ptr());
+    }
+
+    #[test]
+    fn raw_text() {
+        assert_eq!(parse_borrowed("\"a\\tb\" |a b| :my-key").unwrap(), vec![
+            FormRef::String("a\\tb"),
+            FormRef::Symbol("a b"),
+            FormRef::Keyword("my-key"),
+        ]);
+    }
+
+    #[test]
+    fn same_errors() {
+        for source in ["(a b", "(a b]", "{:a}", "()", ")", "#?[:a 1]", "[1 #_]", "#?(:a)", "[:a :b/]", "\"a"] {
+            assert_eq!(parse_borrowed(source).unwrap_err(), parse_str(source).unwrap_err(), "parsing {}", source);
+        }
+    }
+
+    #[test]
+    fn config() {
+        let source = "#?(:a x.y.z :b 1) [#?(:c 2) \"s\" \"t\"]";
+        let config = ParserConfig {
+            features: HashSet::from([Keyword::new("a").unwrap()]),
+            dot_as_method_call: true,
+            ..Default::default()
+        };
+        let borrowed = parse_borrowed_with_config(source, config.clone()).unwrap();
+        let owned = Parser::with_spans(lex_spanned(source).unwrap(), config.clone()).parse_all().unwrap();
+
+        assert!(borrowed.len() == owned.len() && borrowed.iter().zip(&owned).all(|(a, b)| same(a, b)));
+
+        let err = parse_borrowed_with_config(source, ParserConfig { max_nodes: Some(3), ..config.clone() }).unwrap_err();
+        assert_eq!(err.message, "The input has more forms than the limit of 3");
+
+        let err = parse_borrowed_with_config(source, ParserConfig { concat_strings: true, ..config }).unwrap_err();
+        assert_eq!(err.message, "Strings left in the source can't be joined");
+        assert_eq!(err.position, Some(Position { line: 1, column: 33 }));
+    }
+}
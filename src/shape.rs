@@ -0,0 +1,177 @@
+//! Declarative checks of the shape of a [`Form`], for validating configuration-like data.
+use std::fmt::{self, Display, Formatter};
+
+use crate::parser::{Form, FormPath, PathSegment};
+
+/// The expected shape of a form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// Any form.
+    Any,
+    Int,
+    Float,
+    /// An integer or a float.
+    Number,
+    Str,
+    Bool,
+    Keyword,
+    Symbol,
+    Nil,
+    /// A `List` whose elements all have the shape.
+    ListOf(Box<Shape>),
+    /// A `Map` holding every one of `required_keys`, whose values for the keys in `value_shapes`
+    /// have the matching shape. Other keys are allowed, with any value.
+    MapOf { required_keys: Vec<Form>, value_shapes: Vec<(Form, Shape)> },
+    /// A form with any one of the shapes.
+    OneOf(Vec<Shape>),
+}
+
+/// A mismatch between a form and its expected shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeError {
+    /// Where the mismatch is, from the checked form.
+    pub path: FormPath,
+    pub message: String,
+}
+
+/// Checks `form` against `shape`, returning every mismatch found.
+pub fn matches(form: &Form, shape: &Shape) -> Result<(), Vec<ShapeError>> {
+    let mut errors = Vec::new();
+
+    check(form, shape, &mut Vec::new(), &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn check(form: &Form, shape: &Shape, path: &mut Vec<PathSegment>, errors: &mut Vec<ShapeError>) {
+    let mut mismatch = |message: String, path: &[PathSegment]| {
+        errors.push(ShapeError { path: FormPath(path.to_vec()), message });
+    };
+
+    match (shape, form) {
+        (Shape::Any, _)
+        | (Shape::Int, Form::Integer(_))
+        | (Shape::Float, Form::Float(_))
+        | (Shape::Number, Form::Integer(_) | Form::Float(_))
+        | (Shape::Str, Form::String(_))
+        | (Shape::Bool, Form::Bool(_))
+        | (Shape::Keyword, Form::Keyword(_))
+        | (Shape::Symbol, Form::Symbol(_))
+        | (Shape::Nil, Form::Nil) => {},
+        (Shape::ListOf(element), Form::List(forms)) => {
+            for (i, form) in forms.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                check(form, element, path, errors);
+                path.pop();
+            }
+        },
+        (Shape::MapOf { required_keys, value_shapes }, Form::Map(_)) => {
+            for key in required_keys {
+                if form.map_get(key).is_none() {
+                    mismatch(format!("Missing the required key {}", key), path);
+                }
+            }
+
+            for (key, shape) in value_shapes {
+                if let Some(value) = form.map_get(key) {
+                    path.push(PathSegment::MapKey(key.clone()));
+                    check(value, shape, path, errors);
+                    path.pop();
+                }
+            }
+        },
+        (Shape::OneOf(shapes), form) => {
+            if !shapes.iter().any(|shape| matches(form, shape).is_ok()) {
+                mismatch(format!("Expected {}, found {}", shape, form.describe()), path);
+            }
+        },
+        (shape, form) => mismatch(format!("Expected {}, found {}", shape, form.describe()), path),
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Shape::Any => write!(f, "any form"),
+            Shape::Int => write!(f, "an integer"),
+            Shape::Float => write!(f, "a float"),
+            Shape::Number => write!(f, "a number"),
+            Shape::Str => write!(f, "a string"),
+            Shape::Bool => write!(f, "a bool"),
+            Shape::Keyword => write!(f, "a keyword"),
+            Shape::Symbol => write!(f, "a symbol"),
+            Shape::Nil => write!(f, "nil"),
+            Shape::ListOf(element) => write!(f, "a list of {}", element),
+            Shape::MapOf { .. } => write!(f, "a map"),
+            Shape::OneOf(shapes) => {
+                let shapes: Vec<_> = shapes.iter().map(Shape::to_string).collect();
+
+                write!(f, "one of {}", shapes.join(", "))
+            },
+        }
+    }
+}
+
+impl Display for ShapeError {
+    /// Writes the message followed by the path to the mismatch, like `Expected an integer, found a string at :server :port`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.path.0.is_empty() {
+            return write!(f, "{}", self.message);
+        }
+
+        let steps: Vec<_> = self.path.0.iter()
+            .map(|segment| match segment {
+                PathSegment::Index(i) => i.to_string(),
+                PathSegment::MapKey(key) => key.to_string(),
+            })
+            .collect();
+
+        write!(f, "{} at {}", self.message, steps.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_str, Form, FormPath, PathSegment};
+
+    use super::{matches, Shape};
+
+    fn form(source: &str) -> Form {
+        parse_str(source).unwrap().remove(0)
+    }
+
+    fn config() -> Shape {
+        Shape::MapOf {
+            required_keys: vec![form(":port"), form(":host")],
+            value_shapes: vec![
+                (form(":port"), Shape::Int),
+                (form(":host"), Shape::Str),
+                (form(":tags"), Shape::ListOf(Box::new(Shape::OneOf(vec![Shape::Keyword, Shape::Str])))),
+            ],
+        }
+    }
+
+    #[test]
+    fn valid_config() {
+        assert_eq!(matches(&form("{:port 8080 :host \"x\"}"), &config()), Ok(()));
+        assert_eq!(matches(&form("{:port 1 :host \"x\" :tags [:a \"b\"] :extra nil}"), &config()), Ok(()));
+    }
+
+    #[test]
+    fn mismatches() {
+        let errors = matches(&form("{:port \"8080\" :tags [:a 1]}"), &config()).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+
+        assert_eq!(messages, vec![
+            "Missing the required key :host",
+            "Expected an integer, found a string at :port",
+            "Expected one of a keyword, a string, found an integer at :tags 1",
+        ]);
+        assert_eq!(errors[1].path, FormPath(vec![PathSegment::MapKey(form(":port"))]));
+        assert_eq!(matches(&form("[1 2]"), &config()).unwrap_err()[0].message, "Expected a map, found a list");
+    }
+}
@@ -0,0 +1,55 @@
+//! Source text tied to the name of its file, so errors from several files can be told apart.
+use crate::{lexer::{lex_spanned, token::Token, LexError, Span}, parser::{Form, ParseError, Parser, ParserConfig}};
+
+/// The text of a source file along with its name, usually its path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    /// Builds a source file from its name and text.
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+
+    /// Lexes the text into tokens along with their spans. Errors name this file.
+    pub fn lex(&self) -> Result<Vec<(Token, Span)>, LexError> {
+        lex_spanned(&self.text).map_err(|err| err.in_file(&self.name))
+    }
+
+    /// Lexes and parses every form in the text. Errors name this file.
+    pub fn parse(&self) -> Result<Vec<Form>, ParseError> {
+        let tokens = self.lex()?;
+
+        Parser::with_spans(tokens, ParserConfig::default())
+            .parse_all()
+            .map_err(|err| err.in_file(&self.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceFile;
+
+    #[test]
+    fn errors_name_their_file() {
+        let main = SourceFile::new("src/main.lisp", "(print\n  :)");
+        let util = SourceFile::new("src/util.lisp", "(defn f [x]\n  (g x]");
+
+        let err = main.lex().unwrap_err();
+        assert_eq!(err.to_string(), "Empty keyword at src/main.lisp:2:3");
+        assert_eq!(err.render(&main.text), "error: Empty keyword\n --> src/main.lisp:2:3\n  |\n2 |   :)\n  |   ^");
+
+        let err = util.parse().unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected token: `]`, expected `)` at src/util.lisp:2:7");
+        assert!(err.render(&util.text).contains("--> src/util.lisp:2:7"));
+
+        assert_eq!(main.parse().unwrap_err().file.as_deref(), Some("src/main.lisp"));
+        assert!(SourceFile::new("ok.lisp", "(f 1)").parse().is_ok());
+    }
+}
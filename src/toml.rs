@@ -0,0 +1,137 @@
+//! Exporting a [`Form`] to TOML, for configuration written as forms.
+use ::toml::{Table, Value};
+
+use crate::parser::Form;
+
+/// Converts a map into a TOML document, keeping the order of its entries, except that TOML puts
+/// the values of a table before its nested tables.
+///
+/// Maps whose keys are strings or keywords become tables, keyed by the string or keyword name,
+/// lists and sets become arrays, and atoms become scalars:
+/// - symbols become strings prefixed with `'`, like `'foo.bar`, and keywords become strings like `:foo`
+/// - chars become one character strings
+/// - quotes and tags are dropped, leaving the quoted or tagged form
+///
+/// Errors when the form isn't a map or holds a shape TOML can't represent: `nil`, a call, a map key
+/// that isn't a string or keyword, a key given twice, or an array mixing values of different types.
+pub fn to_toml(form: &Form) -> Result<String, String> {
+    let Form::Map(entries) = form else {
+        return Err(format!("A TOML document must be a map, found {}", form.describe()));
+    };
+
+    ::toml::to_string(&to_table(entries, "")?).map_err(|err| err.to_string())
+}
+
+/// Converts a form found at the dotted key `path`, which is used in error messages.
+fn to_value(form: &Form, path: &str) -> Result<Value, String> {
+    match form {
+        Form::Integer(i) => Ok(Value::Integer(*i)),
+        Form::Float(f) => Ok(Value::Float(*f)),
+        Form::String(s) => Ok(Value::String(s.to_string())),
+        Form::Char(c) => Ok(Value::String(c.to_string())),
+        #[cfg(feature = "complex")]
+        Form::Complex { .. } => Ok(Value::String(form.to_string())),
+        Form::Symbol(symbol) => Ok(Value::String(format!("'{}", symbol))),
+        Form::Keyword(k) => Ok(Value::String(k.to_string())),
+        Form::Bool(b) => Ok(Value::Boolean(*b)),
+        Form::List(forms) | Form::Set(forms) => to_array(forms, path),
+        Form::Map(entries) => to_table(entries, path).map(Value::Table),
+        Form::Quote(form) | Form::Tagged { value: form, .. } => to_value(form, path),
+        Form::Nil | Form::Call(_) => Err(format!("TOML can't represent {} at {}", form.describe(), path)),
+    }
+}
+
+fn to_array(forms: &[Form], path: &str) -> Result<Value, String> {
+    let values = forms.iter()
+        .enumerate()
+        .map(|(i, form)| to_value(form, &format!("{}[{}]", path, i)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(first) = values.first() {
+        if let Some(other) = values.iter().find(|value| value.type_str() != first.type_str()) {
+            return Err(format!("A TOML array can't mix {} and {} at {}", first.type_str(), other.type_str(), path));
+        }
+    }
+
+    Ok(Value::Array(values))
+}
+
+fn to_table(entries: &[(Form, Form)], path: &str) -> Result<Table, String> {
+    let mut table = Table::new();
+
+    for (key, value) in entries {
+        let key = match key {
+            Form::String(s) => s.to_string(),
+            Form::Keyword(k) => k.as_str().to_string(),
+            key => {
+                let at = if path.is_empty() { "the top level" } else { path };
+
+                return Err(format!("A TOML key must be a string or keyword, found {} at {}", key.describe(), at));
+            },
+        };
+        let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+        let value = to_value(value, &path)?;
+
+        if table.insert(key, value).is_some() {
+            return Err(format!("The key {} is given twice", path));
+        }
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_str;
+
+    use super::to_toml;
+
+    fn toml_of(source: &str) -> Result<String, String> {
+        to_toml(&parse_str(source).unwrap()[0])
+    }
+
+    #[test]
+    fn nested_config() {
+        let source = "{:name \"rubla\" :port 8080 :level :info :ratio 0.5
+                      :server {:host \"localhost\" :tls {:enabled true}}
+                      :users [{:name \"a\"} {:name \"b\"}]
+                      \"tags\" [\"x\" \"y\"]}";
+
+        assert_eq!(toml_of(source).unwrap(), "\
+name = \"rubla\"
+port = 8080
+level = \":info\"
+ratio = 0.5
+tags = [\"x\", \"y\"]
+
+[server]
+host = \"localhost\"
+
+[server.tls]
+enabled = true
+
+[[users]]
+name = \"a\"
+
+[[users]]
+name = \"b\"
+");
+    }
+
+    #[test]
+    fn unrepresentable() {
+        let cases = vec![
+            ("[1 2]", "A TOML document must be a map, found a list"),
+            ("{:a nil}", "TOML can't represent nil at a"),
+            ("{:a {:b [1 (f)]}}", "TOML can't represent a call at a.b[1]"),
+            ("{:a [1 \"2\"]}", "A TOML array can't mix integer and string at a"),
+            ("{1 2}", "A TOML key must be a string or keyword, found an integer at the top level"),
+            ("{:a {[1] 2}}", "A TOML key must be a string or keyword, found a list at a"),
+            ("{:a 1 \"a\" 2}", "The key a is given twice"),
+        ];
+
+        for (source, message) in cases {
+            assert_eq!(toml_of(source).unwrap_err(), message, "exporting `{}`", source);
+        }
+    }
+}